@@ -0,0 +1,34 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::Criterion;
+use imp::parser::*;
+use imp::transformers::*;
+
+/// Returns `n` from imports spread over a tenth as many modules, and as many
+/// plain imports of three modules each.
+fn source(n: usize) -> String {
+   let mut src = String::new();
+   for i in 0..n {
+      src += &format!("from .m{} import n{i}  # c{i}\n", i % (n / 10).max(1));
+      src += &format!("import a{i}, b{i}, c{i}\n");
+   }
+   src
+}
+
+fn transformers(c: &mut Criterion) {
+   for n in [100, 1000, 10000] {
+      let src = source(n);
+      let pd = Pd::new(&src);
+      let imports: Vec<_> = pd.start(&mut Ps::new()).unwrap().into_iter().filter_map(Item::into_import).collect();
+      c.bench_function(&format!("combine_relative_imports {n}"), |b| {
+         b.iter_batched(|| imports.clone(), |mut imports| combine_relative_imports(&mut imports), BatchSize::SmallInput)
+      });
+      c.bench_function(&format!("separate_absolute_imports {n}"), |b| {
+         b.iter_batched(|| imports.clone(), |mut imports| separate_absolute_imports(&mut imports), BatchSize::SmallInput)
+      });
+   }
+}
+
+criterion_group!(benches, transformers);
+criterion_main!(benches);
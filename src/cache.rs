@@ -0,0 +1,136 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::config::*;
+use crate::error::*;
+
+/// The files found to be sorted by previous runs, stored in `DIR/index` as a
+/// line with the hash of the version of imp and the configuration followed by
+/// one line per file.
+pub struct Cache {
+   index: PathBuf,
+   config: u64,
+   entries: HashMap<PathBuf, Entry>
+}
+
+#[derive(PartialEq, Eq)]
+struct Entry {
+   mtime: u128,
+   size: u64,
+   hash: u64
+}
+
+impl Cache {
+   /// Loads the cache in `dir`, which is empty if it doesn't exist yet or was
+   /// written with different options or by another version of imp, which may
+   /// sort differently.
+   pub fn load(dir: &Path, cfg: &Config) -> Self {
+      Self::load_version(dir, cfg, env!("CARGO_PKG_VERSION"))
+   }
+
+   fn load_version(dir: &Path, cfg: &Config, version: &str) -> Self {
+      let config = hash(&(version, fingerprint(cfg)));
+      let mut cache = Self { index: dir.join("index"), config, entries: HashMap::new() };
+      let Ok(index) = fs::read_to_string(&cache.index) else { return cache };
+      let mut lines = index.lines();
+      if lines.next() != Some(&cache.config.to_string()) {
+         return cache;
+      }
+      for line in lines {
+         let mut fields = line.splitn(4, ' ');
+         let (Some(mtime), Some(size), Some(hash), Some(path)) = (fields.next(), fields.next(), fields.next(), fields.next())
+         else {
+            continue;
+         };
+         if let (Ok(mtime), Ok(size), Ok(hash)) = (mtime.parse(), size.parse(), hash.parse()) {
+            cache.entries.insert(path.into(), Entry { mtime, size, hash });
+         }
+      }
+      cache
+   }
+
+   /// Returns whether the file at `path` is unchanged since it was found to be
+   /// sorted. A file whose modification time changed is read to compare its
+   /// contents.
+   pub fn is_sorted(&self, path: &Path) -> bool {
+      let Some(entry) = self.entries.get(path) else { return false };
+      let Ok((mtime, size)) = metadata(path) else { return false };
+      if (mtime, size) == (entry.mtime, entry.size) {
+         return true;
+      }
+      size == entry.size && fs::read(path).is_ok_and(|src| hash(&src) == entry.hash)
+   }
+
+   /// Records that the file at `path` is sorted.
+   pub fn insert(&mut self, path: &Path) -> Result<(), ImpError> {
+      let (mtime, size) = metadata(path).map_err(|err| ImpError::Io(path.into(), err))?;
+      let src = fs::read(path).map_err(|err| ImpError::Io(path.into(), err))?;
+      self.entries.insert(path.into(), Entry { mtime, size, hash: hash(&src) });
+      Ok(())
+   }
+
+   pub fn save(&self) -> Result<(), ImpError> {
+      let mut index = format!("{}\n", self.config);
+      for (path, entry) in &self.entries {
+         writeln!(index, "{} {} {} {}", entry.mtime, entry.size, entry.hash, path.display()).unwrap();
+      }
+      if let Some(dir) = self.index.parent() {
+         fs::create_dir_all(dir).map_err(|err| ImpError::Io(dir.into(), err))?;
+      }
+      fs::write(&self.index, index).map_err(|err| ImpError::Io(self.index.clone(), err))
+   }
+}
+
+fn metadata(path: &Path) -> std::io::Result<(u128, u64)> {
+   let metadata = fs::metadata(path)?;
+   let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |mtime| mtime.as_nanos());
+   Ok((mtime, metadata.len()))
+}
+
+/// Describes the options which affect how files are sorted.
+fn fingerprint(cfg: &Config) -> String {
+   let cfg = Config {
+      paths: vec![],
+      code: None,
+      check: false,
+      in_place: false,
+      interactive: false,
+      quiet: false,
+      verbose: false,
+      cache: None,
+      stdin_filename: None,
+      ..cfg.clone()
+   };
+   format!("{cfg:?}")
+}
+
+fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+   let mut hasher = DefaultHasher::new();
+   value.hash(&mut hasher);
+   hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::test_dir::Dir;
+
+   #[test]
+   fn other_versions_dont_use_the_cache() {
+      let dir = Dir::new("cache-version", &[("a.py", "import a\n")]);
+      let (path, cfg) = (dir.0.join("a.py"), Config::default());
+      let mut cache = Cache::load_version(&dir.0, &cfg, "1.0.0");
+      cache.insert(&path).unwrap();
+      cache.save().unwrap();
+      assert!(Cache::load_version(&dir.0, &cfg, "1.0.0").is_sorted(&path));
+      assert!(!Cache::load_version(&dir.0, &cfg, "1.1.0").is_sorted(&path));
+      assert!(!Cache::load(&dir.0, &cfg).is_sorted(&path));
+   }
+}
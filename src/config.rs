@@ -0,0 +1,361 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::error::*;
+
+/// Names accepted by `--profile`.
+pub const PROFILES: &[&str] = &["imp", "none"];
+
+#[derive(Clone, Debug)]
+pub struct Config {
+   pub paths: Vec<PathBuf>,
+   /// Source to format instead of files.
+   pub code: Option<String>,
+   /// Report unsorted files instead of printing them.
+   pub check: bool,
+   /// Overwrite files instead of printing them.
+   pub in_place: bool,
+   /// Show the changes to each file and ask before writing them.
+   pub interactive: bool,
+   pub quiet: bool,
+   pub verbose: bool,
+   /// Directory to remember sorted files in, so they are skipped until they
+   /// change.
+   pub cache: Option<PathBuf>,
+   /// Name of the file piped to stdin, used in messages and to find its
+   /// package.
+   pub stdin_filename: Option<PathBuf>,
+   /// Number of blank lines between the imports and the rest of the file,
+   /// `-1` keeps the number of blank lines found in the source.
+   pub lines_after_imports: isize,
+   /// Add `from __future__ import annotations`.
+   pub add_future_annotations: bool,
+   /// Place `from __future__` imports before all others.
+   pub future_first: bool,
+   /// Combine from imports of the same module.
+   pub combine_from_imports: bool,
+   /// Split `import a, b` into one import per module.
+   pub separate_imports: bool,
+   /// Only sort the names inside parenthesized from imports, leaving
+   /// everything else as written.
+   pub sort_within_parens_only: bool,
+   /// Place `from x import ...` right after `import x`.
+   pub group_from_imports: bool,
+   /// Modules placed before the others of their kind, in this order.
+   pub force_to_top: Vec<String>,
+   /// Sort the imports of an `if TYPE_CHECKING:` block right after the
+   /// imports.
+   pub sort_type_checking: bool,
+   /// Sort module paths ignoring case.
+   pub sort_case_modules: bool,
+   /// Sort the names of from imports ignoring case.
+   pub sort_case_identifiers: bool,
+   /// Order imported names as constants, classes, then functions.
+   pub order_by_type: bool,
+   /// Print the names of a from import one per line inside parentheses if its
+   /// line is longer than this.
+   pub line_length: Option<usize>,
+   /// Print every from import on a single line, moving the comments of its
+   /// names after it.
+   pub no_wrap: bool,
+   /// Indentation of the names of wrapped from imports.
+   pub wrap_indent: String,
+   /// Measure `--line-length` in terminal columns rather than bytes, wide
+   /// characters count as two.
+   pub unicode_width: bool,
+   /// Number of spaces between an import and its comment.
+   pub comment_spacing: usize,
+   /// Keep the spaces written between an import and its comment.
+   pub preserve_comment_spacing: bool,
+   /// Put exactly one space after the `#` of comments, `#:` and `#!` are kept.
+   pub normalize_comments: bool,
+   /// Put comments on the line above their import.
+   pub no_inline_comments: bool,
+   /// Remove the comments of imports.
+   pub trim_comments: bool,
+   /// Move names with a comment such as `# noqa` to a from import of their own.
+   pub split_noqa: bool,
+   /// Remove aliases such as `import os as os` and `from x import a as a`.
+   pub remove_redundant_aliases: bool,
+   /// Print the names of a from import in descending order.
+   pub reverse_identifiers: bool,
+   /// Rewrite relative imports into absolute imports of the file's package.
+   pub relative_to_absolute: bool,
+   /// Rewrite from imports of the file's package into relative imports.
+   pub absolute_to_relative: bool,
+   /// Remove imports of names which are bound again later in the file.
+   pub remove_shadowed: bool,
+   /// Print the parsed imports of each file.
+   pub debug_ast: bool,
+   /// Warn about names bound by two different imports.
+   pub warn_collisions: bool,
+   /// Warn if the file is indented with tabs and `--wrap-indent` with spaces,
+   /// or the other way around.
+   pub warn_mixed_indent: bool
+}
+
+impl Default for Config {
+   fn default() -> Self {
+      Self {
+         paths: vec![],
+         code: None,
+         check: false,
+         in_place: false,
+         interactive: false,
+         quiet: false,
+         verbose: false,
+         cache: None,
+         stdin_filename: None,
+         lines_after_imports: 2,
+         add_future_annotations: true,
+         future_first: true,
+         combine_from_imports: true,
+         separate_imports: true,
+         sort_within_parens_only: false,
+         group_from_imports: false,
+         force_to_top: vec![],
+         sort_type_checking: false,
+         sort_case_modules: false,
+         sort_case_identifiers: false,
+         order_by_type: false,
+         line_length: None,
+         no_wrap: false,
+         wrap_indent: "    ".into(),
+         unicode_width: false,
+         comment_spacing: 2,
+         preserve_comment_spacing: false,
+         normalize_comments: false,
+         no_inline_comments: false,
+         trim_comments: false,
+         split_noqa: false,
+         remove_redundant_aliases: false,
+         reverse_identifiers: false,
+         relative_to_absolute: false,
+         absolute_to_relative: false,
+         remove_shadowed: false,
+         debug_ast: false,
+         warn_collisions: false,
+         warn_mixed_indent: false
+      }
+   }
+}
+
+impl Config {
+   pub fn from_args<I>(args: I) -> Result<Self, ImpError>
+   where I: IntoIterator<Item = String> {
+      let mut cfg = Self::default();
+      let mut args = args.into_iter();
+      while let Some(arg) = args.next() {
+         match arg.as_str() {
+            "--profile" => {
+               let name: String = value(&arg, args.next())?;
+               if !cfg.profile(&name) {
+                  let suggestion = closest(&name, PROFILES);
+                  return Err(ImpError::UnknownProfile { name, suggestion });
+               }
+            },
+            "--lines-after-imports" => {
+               cfg.lines_after_imports = value(&arg, args.next())?;
+               if cfg.lines_after_imports < -1 {
+                  return Err(ImpError::InvalidValue { flag: arg, value: cfg.lines_after_imports.to_string() });
+               }
+            },
+            "--code" | "-c" => cfg.code = Some(value(&arg, args.next())?),
+            "--check" => cfg.check = true,
+            "--in-place" | "-i" => cfg.in_place = true,
+            "--interactive" => cfg.interactive = true,
+            "--quiet" | "-q" => cfg.quiet = true,
+            "--verbose" | "-v" => cfg.verbose = true,
+            "--stdin-filename" => cfg.stdin_filename = Some(value(&arg, args.next())?),
+            "--cache" => cfg.cache = Some(value(&arg, args.next())?),
+            "--no-future-first" => cfg.future_first = false,
+            "--sort-within-parens-only" => cfg.sort_within_parens_only = true,
+            "--group-from-imports" => cfg.group_from_imports = true,
+            "--force-to-top" => {
+               let modules: String = value(&arg, args.next())?;
+               cfg.force_to_top.extend(modules.split(',').map(str::trim).filter(|module| !module.is_empty()).map(String::from));
+            },
+            "--sort-type-checking" => cfg.sort_type_checking = true,
+            "--sort-case-modules" => cfg.sort_case_modules = true,
+            "--sort-case-identifiers" => cfg.sort_case_identifiers = true,
+            "--order-by-type" => cfg.order_by_type = true,
+            "--line-length" => cfg.line_length = Some(value(&arg, args.next())?),
+            "--no-wrap" => {
+               cfg.no_wrap = true;
+               cfg.combine_from_imports = true;
+            },
+            "--wrap-indent" => cfg.wrap_indent = indent(&arg, args.next())?,
+            "--unicode-width" => cfg.unicode_width = true,
+            "--comment-spacing" => cfg.comment_spacing = value(&arg, args.next())?,
+            "--preserve-comment-spacing" => cfg.preserve_comment_spacing = true,
+            "--normalize-comments" => cfg.normalize_comments = true,
+            "--no-inline-comments" => cfg.no_inline_comments = true,
+            "--trim-comments" => cfg.trim_comments = true,
+            "--split-noqa" => cfg.split_noqa = true,
+            "--remove-redundant-aliases" => cfg.remove_redundant_aliases = true,
+            "--reverse-identifiers" => cfg.reverse_identifiers = true,
+            "--relative-to-absolute" => cfg.relative_to_absolute = true,
+            "--absolute-to-relative" => cfg.absolute_to_relative = true,
+            "--remove-shadowed" => cfg.remove_shadowed = true,
+            "--debug-ast" => cfg.debug_ast = true,
+            "--warn-collisions" => cfg.warn_collisions = true,
+            "--warn-mixed-indent" => cfg.warn_mixed_indent = true,
+            flag if flag.starts_with("--") => return Err(ImpError::UnknownFlag(arg)),
+            _ => cfg.paths.push(arg.into())
+         }
+      }
+      Ok(cfg)
+   }
+
+   /// Applies the options of a named profile, returns `false` if there is no
+   /// such profile. `imp` is the default, `none` only sorts the imports.
+   pub fn profile(&mut self, name: &str) -> bool {
+      let default = Self::default();
+      match name {
+         "imp" => {
+            self.add_future_annotations = default.add_future_annotations;
+            self.combine_from_imports = default.combine_from_imports;
+            self.separate_imports = default.separate_imports;
+            self.group_from_imports = default.group_from_imports;
+            self.lines_after_imports = default.lines_after_imports;
+         },
+         "none" => {
+            self.add_future_annotations = false;
+            self.combine_from_imports = false;
+            self.separate_imports = false;
+            self.group_from_imports = false;
+            self.lines_after_imports = -1;
+         },
+         _ => return false
+      }
+      true
+   }
+
+   /// Rejects combinations of options which contradict each other.
+   pub fn validate(&self) -> Result<(), ImpError> {
+      let modes = [
+         ("--code", self.code.is_some()),
+         ("--check", self.check),
+         ("--in-place", self.in_place),
+         ("--interactive", self.interactive)
+      ];
+      let modes: Vec<&str> = modes.into_iter().filter_map(|(flag, set)| set.then_some(flag)).collect();
+      if let [first, second, ..] = modes[..] {
+         return Err(ImpError::Conflict(first.into(), second.into()));
+      }
+      if let (Some(_), Some(path)) = (&self.code, self.paths.first()) {
+         return Err(ImpError::Conflict("--code".into(), path.display().to_string()));
+      }
+      if self.relative_to_absolute && self.absolute_to_relative {
+         return Err(ImpError::Conflict("--relative-to-absolute".into(), "--absolute-to-relative".into()));
+      }
+      if self.no_wrap && self.line_length.is_some() {
+         return Err(ImpError::Conflict("--no-wrap".into(), "--line-length".into()));
+      }
+      if self.quiet && self.verbose {
+         return Err(ImpError::Conflict("--quiet".into(), "--verbose".into()));
+      }
+      if self.interactive && self.paths.is_empty() {
+         return Err(ImpError::NeedsPaths("--interactive".into()));
+      }
+      if self.in_place && self.paths.is_empty() {
+         return Err(ImpError::NeedsPaths("--in-place".into()));
+      }
+      Ok(())
+   }
+
+   /// Number of blank lines to print after the imports, given the number of
+   /// blank lines found in the source.
+   pub fn blank_lines_after_imports(&self, original: usize) -> usize {
+      if self.lines_after_imports < 0 {
+         original
+      } else {
+         self.lines_after_imports as usize
+      }
+   }
+}
+
+fn value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, ImpError> {
+   let value = value.ok_or_else(|| ImpError::MissingValue(flag.into()))?;
+   value.parse().map_err(|_| ImpError::InvalidValue { flag: flag.into(), value })
+}
+
+/// Parses the value of `--wrap-indent`, `tab` or a number of spaces.
+fn indent(flag: &str, value: Option<String>) -> Result<String, ImpError> {
+   match value.as_deref() {
+      Some("tab") => Ok("\t".into()),
+      _ => Ok(" ".repeat(self::value(flag, value)?))
+   }
+}
+
+/// Returns the candidate closest to `name` if it is only a typo away, at most
+/// two characters inserted, removed or replaced.
+fn closest(name: &str, candidates: &[&'static str]) -> Option<&'static str> {
+   candidates
+      .iter()
+      .map(|candidate| (edit_distance(name, candidate), *candidate))
+      .filter(|(distance, _)| *distance <= 2)
+      .min()
+      .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+   let b: Vec<char> = b.chars().collect();
+   let mut previous: Vec<usize> = (0..=b.len()).collect();
+   for (i, a) in a.chars().enumerate() {
+      let mut current = vec![i + 1];
+      for (j, b) in b.iter().enumerate() {
+         current.push((previous[j] + (a != *b) as usize).min(previous[j + 1] + 1).min(current[j] + 1));
+      }
+      previous = current;
+   }
+   previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn validate(args: &[&str]) -> Result<(), ImpError> {
+      Config::from_args(args.iter().map(|arg| arg.to_string()))?.validate()
+   }
+
+   fn conflict(args: &[&str]) -> Option<(String, String)> {
+      match validate(args) {
+         Err(ImpError::Conflict(a, b)) => Some((a, b)),
+         _ => None
+      }
+   }
+
+   #[test]
+   fn contradictory_options() {
+      let pair = |a: &str, b: &str| Some((a.to_string(), b.to_string()));
+      assert_eq!(conflict(&["--check", "-i", "x.py"]), pair("--check", "--in-place"));
+      assert_eq!(conflict(&["--code", "import a", "x.py"]), pair("--code", "x.py"));
+      assert_eq!(
+         conflict(&["--relative-to-absolute", "--absolute-to-relative"]),
+         pair("--relative-to-absolute", "--absolute-to-relative")
+      );
+      assert_eq!(conflict(&["-q", "-v"]), pair("--quiet", "--verbose"));
+      assert_eq!(conflict(&["--line-length", "80", "--no-wrap"]), pair("--no-wrap", "--line-length"));
+      assert!(validate(&["--check", "-q", "x.py"]).is_ok());
+   }
+
+   #[test]
+   fn in_place_needs_paths() {
+      assert!(matches!(validate(&["-i"]), Err(ImpError::NeedsPaths(flag)) if flag == "--in-place"));
+      assert!(matches!(validate(&["--interactive"]), Err(ImpError::NeedsPaths(flag)) if flag == "--interactive"));
+      assert!(validate(&["-i", "x.py"]).is_ok());
+      assert!(validate(&["--check"]).is_ok());
+   }
+
+   #[test]
+   fn unknown_profile_suggests_the_closest() {
+      let error = validate(&["--profile", "imo"]).unwrap_err();
+      assert!(matches!(&error, ImpError::UnknownProfile { name, suggestion: Some("imp") } if name == "imo"));
+      assert_eq!(error.to_string(), "unknown profile `imo`, expected one of `imp`, `none` (did you mean `imp`?)");
+      assert!(matches!(validate(&["--profile", "blahk"]), Err(ImpError::UnknownProfile { suggestion: None, .. })));
+      assert!(validate(&["--profile", "none"]).is_ok());
+      assert!(validate(&["--profile", "imp"]).is_ok());
+   }
+}
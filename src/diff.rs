@@ -0,0 +1,44 @@
+use std::fmt::Write;
+
+/// Returns a unified diff of the lines of `old` and `new` with a single hunk
+/// spanning the changed lines, or an empty string if they are equal.
+pub fn diff(path: &str, old: &str, new: &str) -> String {
+   let old: Vec<&str> = old.lines().collect();
+   let new: Vec<&str> = new.lines().collect();
+   let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+   let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+   let old_lines = &old[prefix..old.len() - suffix];
+   let new_lines = &new[prefix..new.len() - suffix];
+   if old_lines.is_empty() && new_lines.is_empty() {
+      return String::new();
+   }
+   // lcs[i][j] is the length of the longest common subsequence of
+   // old_lines[i..] and new_lines[j..].
+   let mut lcs = vec![vec![0; new_lines.len() + 1]; old_lines.len() + 1];
+   for i in (0..old_lines.len()).rev() {
+      for j in (0..new_lines.len()).rev() {
+         lcs[i][j] = if old_lines[i] == new_lines[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+      }
+   }
+   let mut out = String::new();
+   writeln!(out, "--- {path}").unwrap();
+   writeln!(out, "+++ {path}").unwrap();
+   let start = |len: usize| if len == 0 { prefix } else { prefix + 1 };
+   writeln!(out, "@@ -{},{} +{},{} @@", start(old_lines.len()), old_lines.len(), start(new_lines.len()), new_lines.len())
+      .unwrap();
+   let (mut i, mut j) = (0, 0);
+   while i < old_lines.len() || j < new_lines.len() {
+      if i < old_lines.len() && j < new_lines.len() && old_lines[i] == new_lines[j] {
+         writeln!(out, " {}", old_lines[i]).unwrap();
+         i += 1;
+         j += 1;
+      } else if i < old_lines.len() && (j == new_lines.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+         writeln!(out, "-{}", old_lines[i]).unwrap();
+         i += 1;
+      } else {
+         writeln!(out, "+{}", new_lines[j]).unwrap();
+         j += 1;
+      }
+   }
+   out
+}
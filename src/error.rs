@@ -0,0 +1,49 @@
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::PROFILES;
+
+#[derive(Debug)]
+pub enum ImpError {
+   UnknownFlag(String),
+   MissingValue(String),
+   InvalidValue {
+      flag: String,
+      value: String
+   },
+   /// A `--profile` which doesn't exist, with the closest existing one.
+   UnknownProfile {
+      name: String,
+      suggestion: Option<&'static str>
+   },
+   Conflict(String, String),
+   NeedsPaths(String),
+   Io(PathBuf, io::Error)
+}
+
+impl Display for ImpError {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+         Self::UnknownFlag(flag) => write!(f, "unknown flag `{flag}`"),
+         Self::MissingValue(flag) => write!(f, "`{flag}` expects a value"),
+         Self::InvalidValue { flag, value } => write!(f, "invalid value `{value}` for `{flag}`"),
+         Self::UnknownProfile { name, suggestion } => {
+            write!(f, "unknown profile `{name}`, expected one of ")?;
+            for (i, profile) in PROFILES.iter().enumerate() {
+               write!(f, "{}`{profile}`", if i == 0 { "" } else { ", " })?;
+            }
+            match suggestion {
+               Some(suggestion) => write!(f, " (did you mean `{suggestion}`?)"),
+               None => Ok(())
+            }
+         },
+         Self::Conflict(a, b) => write!(f, "`{a}` can't be used with `{b}`"),
+         Self::NeedsPaths(flag) => write!(f, "`{flag}` needs files to format"),
+         Self::Io(path, err) => write!(f, "{}: {err}", path.display())
+      }
+   }
+}
+
+impl std::error::Error for ImpError {}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::fs;
+use std::fs::canonicalize;
+use std::fs::read_dir;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::error::*;
+
+/// Expands directories into the Python files they contain, files are kept as
+/// given.
+pub fn python_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ImpError> {
+   let mut files = vec![];
+   for path in paths {
+      if path.is_dir() {
+         let mut entries = vec![];
+         for entry in read_dir(path).map_err(|err| ImpError::Io(path.clone(), err))? {
+            let entry = entry.map_err(|err| ImpError::Io(path.clone(), err))?.path();
+            if entry.is_dir() || entry.extension().is_some_and(|extension| extension == "py") {
+               entries.push(entry);
+            }
+         }
+         entries.sort();
+         files.extend(python_files(&entries)?);
+      } else {
+         files.push(path.clone());
+      }
+   }
+   Ok(files)
+}
+
+/// Returns the dotted name of the package containing the file at `path`, found
+/// by walking up the directories which contain an `__init__.py`. A file outside
+/// of any package has an empty name.
+pub fn package(path: &Path) -> Vec<String> {
+   let mut package = vec![];
+   let Ok(path) = canonicalize(path) else { return package };
+   let mut dir = path.parent();
+   while let Some(d) = dir.filter(|d| d.join("__init__.py").is_file()) {
+      let Some(name) = d.file_name() else { break };
+      package.push(name.to_string_lossy().into_owned());
+      dir = d.parent();
+   }
+   package.reverse();
+   package
+}
+
+/// Reads the Python file at `path`, see `decode`.
+pub fn read_source(path: &Path) -> Result<String, ImpError> {
+   fs::read(path).and_then(decode).map_err(|err| ImpError::Io(path.into(), err))
+}
+
+/// Writes the Python source `src` formatted from `original` to `path`, see
+/// `encode`.
+pub fn write_source(path: &Path, src: &str, original: &str) -> Result<(), ImpError> {
+   fs::write(path, encode(src, original)).map_err(|err| ImpError::Io(path.into(), err))
+}
+
+/// Decodes Python source. With the `encoding` feature, the encoding declared
+/// by a PEP 263 coding comment on one of the first two lines is used,
+/// otherwise the source must be UTF-8.
+pub fn decode(src: Vec<u8>) -> io::Result<String> {
+   #[cfg(feature = "encoding")]
+   if let Some(encoding) = declared_encoding(&src) {
+      return encoding.decode_without_bom_handling_and_without_replacement(&src).map(Cow::into_owned).ok_or_else(|| {
+         io::Error::new(io::ErrorKind::InvalidData, format!("stream did not contain valid {}", encoding.name()))
+      });
+   }
+   String::from_utf8(src).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Encodes Python source formatted from `original` in the encoding `original`
+/// declares, see `decode`.
+#[cfg_attr(not(feature = "encoding"), allow(unused_variables))]
+pub fn encode<'a>(src: &'a str, original: &str) -> Cow<'a, [u8]> {
+   #[cfg(feature = "encoding")]
+   if let Some(encoding) = declared_encoding(original.as_bytes()) {
+      return encoding.encode(src).0;
+   }
+   Cow::Borrowed(src.as_bytes())
+}
+
+/// Finds the encoding named by a `coding: NAME` or `coding=NAME` comment on
+/// one of the first two lines. Names are looked up as WHATWG labels, after
+/// removing dashes and underscores if they don't match as written, so
+/// `latin-1` is read as `windows-1252` like browsers do.
+#[cfg(feature = "encoding")]
+fn declared_encoding(src: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+   for line in src.split(|c| *c == b'\n').take(2) {
+      let line = line.trim_ascii_start();
+      if !line.starts_with(b"#") {
+         continue;
+      }
+      let Some(at) = line.windows(7).position(|w| w == b"coding:" || w == b"coding=") else { continue };
+      let name = line[at + 7..].trim_ascii_start();
+      let name = &name[..name.iter().take_while(|c| c.is_ascii_alphanumeric() || b"-_.".contains(c)).count()];
+      let bare: Vec<u8> = name.iter().filter(|c| !b"-_".contains(c)).copied().collect();
+      return encoding_rs::Encoding::for_label(name).or_else(|| encoding_rs::Encoding::for_label(&bare));
+   }
+   None
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const LATIN_1: &[u8] = b"# -*- coding: latin-1 -*-\nimport b  # caf\xe9\nimport a\n";
+
+   #[cfg(feature = "encoding")]
+   #[test]
+   fn declared_encoding_round_trips() {
+      let src = decode(LATIN_1.to_vec()).unwrap();
+      assert_eq!(src, "# -*- coding: latin-1 -*-\nimport b  # caf\u{e9}\nimport a\n");
+      assert_eq!(encode(&src, &src), LATIN_1);
+      let cfg = crate::config::Config::from_args(["--profile", "none"].map(String::from)).unwrap();
+      let sorted = crate::sort_imports(&src, &cfg);
+      assert_eq!(encode(&sorted, &src), &b"# -*- coding: latin-1 -*-\nimport a\nimport b  # caf\xe9\n"[..]);
+      assert_eq!(
+         decode(b"\n# vim: set fileencoding=utf-8 :\n\xc3\xa9".to_vec()).unwrap(),
+         "\n# vim: set fileencoding=utf-8 :\n\u{e9}"
+      );
+      assert!(decode(b"# coding: utf-8\n\xe9".to_vec()).is_err());
+   }
+
+   #[cfg(not(feature = "encoding"))]
+   #[test]
+   fn sources_must_be_utf8() {
+      assert_eq!(decode(LATIN_1.to_vec()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+      assert_eq!(encode("import a\n", "# coding: latin-1\n"), &b"import a\n"[..]);
+   }
+}
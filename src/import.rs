@@ -6,10 +6,11 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::{self};
 use std::hash::Hash;
-use std::str;
 
 use itertools::Itertools;
+use unicode_width::UnicodeWidthStr;
 
+use crate::config::*;
 use crate::parser::*;
 
 pub type ModulePath<'a> = Vec<Token<'a>>;
@@ -20,16 +21,103 @@ pub struct Module<'a> {
    pub alias: Option<Token<'a>>
 }
 
+/// A name imported by a from import, ordered by the name before the alias.
+/// The comments written on the lines above it and after it inside
+/// parentheses aren't compared.
+#[derive(Clone, Debug)]
+pub struct Identifier<'a> {
+   pub name: Token<'a>,
+   pub alias: Option<Token<'a>>,
+   pub leading: CommentList<'a>,
+   pub comments: CommentList<'a>
+}
+
 pub type ModuleList<'a> = BTreeSet<Module<'a>>;
-pub type IdentifierList<'a> = BTreeSet<Token<'a>>;
+pub type IdentifierList<'a> = BTreeSet<Identifier<'a>>;
+/// Comments of an import. Trailing comments start with the spaces written
+/// before them, comments on the lines above an import start with the `#`.
+pub type CommentList<'a> = Vec<Token<'a>>;
 
 impl<'a> Display for Module<'a> {
    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
       for i in self.path.iter().map(|token| token.slice).intersperse(b".") {
-         write!(f, "{}", str::from_utf8(i).unwrap())?
+         write!(f, "{}", String::from_utf8_lossy(i))?
       }
       if let Some(alias) = &self.alias {
-         write!(f, " as {}", str::from_utf8(alias.slice).unwrap())?
+         write!(f, " as {}", alias.to_string_lossy())?
+      }
+      Ok(())
+   }
+}
+
+impl<'a> Identifier<'a> {
+   /// Returns the tokens of the identifier, the name followed by the alias.
+   pub fn tokens(&self) -> impl Iterator<Item = &Token<'a>> {
+      std::iter::once(&self.name).chain(&self.alias)
+   }
+
+   pub fn has_comments(&self) -> bool {
+      !(self.leading.is_empty() && self.comments.is_empty())
+   }
+}
+
+/// Adds a name to a from import, keeping the comments of both if the name is
+/// already imported.
+pub fn insert_identifier<'a>(identifiers: &mut IdentifierList<'a>, mut identifier: Identifier<'a>) {
+   if let Some(existing) = identifiers.take(&identifier) {
+      identifier.leading = [existing.leading, identifier.leading].concat();
+      identifier.comments = [existing.comments, identifier.comments].concat();
+      dedup_comments(&mut identifier.leading);
+      dedup_comments(&mut identifier.comments);
+   }
+   identifiers.insert(identifier);
+}
+
+/// Sorts comments into source order, removing the ones with the same text
+/// as an earlier one.
+pub fn dedup_comments(comments: &mut CommentList) {
+   comments.sort_by_key(|comment| comment.i);
+   let mut seen = vec![];
+   comments.retain(|comment| {
+      let text = comment.slice.trim_ascii_start();
+      let unique = !seen.contains(&text);
+      seen.push(text);
+      unique
+   });
+}
+
+impl<'a> Ord for Identifier<'a> {
+   fn cmp(&self, other: &Self) -> Ordering {
+      self.name.cmp(&other.name).then_with(|| self.alias.cmp(&other.alias))
+   }
+}
+
+impl<'a> PartialOrd for Identifier<'a> {
+   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+      Some(self.cmp(other))
+   }
+}
+
+impl<'a> PartialEq for Identifier<'a> {
+   fn eq(&self, other: &Self) -> bool {
+      self.name == other.name && self.alias == other.alias
+   }
+}
+
+impl<'a> Eq for Identifier<'a> {}
+
+impl<'a> Hash for Identifier<'a> {
+   fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+      self.name.hash(state);
+      self.alias.hash(state);
+   }
+}
+
+impl<'a> Display for Identifier<'a> {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+      write!(f, "{}", self.name.to_string_lossy())?;
+      if let Some(alias) = &self.alias {
+         write!(f, " as {}", alias.to_string_lossy())?
       }
       Ok(())
    }
@@ -42,6 +130,13 @@ pub enum RelativeModule<'a> {
 }
 
 impl<'a> RelativeModule<'a> {
+   pub fn path(&self) -> &[Token<'a>] {
+      match self {
+         Self::Named { path, .. } => path,
+         Self::Unnamed { .. } => &[]
+      }
+   }
+
    fn is_future(&'a self) -> bool {
       matches!(self, Self::Named { level: 0, path } if path.first().is_some_and(|v| v.slice == "__future__".as_bytes()))
    }
@@ -86,7 +181,7 @@ impl<'a> Display for RelativeModule<'a> {
                write!(f, ".")?
             }
             for i in path.iter().map(|token| token.slice).intersperse(b".") {
-               write!(f, "{}", str::from_utf8(i).unwrap())?
+               write!(f, "{}", String::from_utf8_lossy(i))?
             }
          },
          Self::Unnamed { level } =>
@@ -98,25 +193,234 @@ impl<'a> Display for RelativeModule<'a> {
    }
 }
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+/// An import statement, ordered by `Ord` and only equal to the imports it is
+/// ordered the same as, whatever their comments.
+#[derive(Clone, Debug)]
 pub enum Import<'a> {
-   Absolute { modules: ModuleList<'a>, comment: Option<Token<'a>> },
-   Relative { from: RelativeModule<'a>, identifiers: IdentifierList<'a>, comment: Option<Token<'a>> },
-   Wildcard { from: RelativeModule<'a>, comment: Option<Token<'a>> }
+   Absolute { modules: ModuleList<'a>, leading: CommentList<'a>, comments: CommentList<'a> },
+   Relative { from: RelativeModule<'a>, identifiers: IdentifierList<'a>, leading: CommentList<'a>, comments: CommentList<'a> },
+   Wildcard { from: RelativeModule<'a>, leading: CommentList<'a>, comments: CommentList<'a> }
 }
 
-impl<'a> Ord for Import<'a> {
-   fn cmp(&self, other: &Self) -> Ordering {
+/// The kind of an import, without what it imports.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum ImportKind {
+   Absolute,
+   Relative,
+   Wildcard
+}
+
+impl<'a> Import<'a> {
+   /// Returns the kind of the import.
+   pub fn kind(&self) -> ImportKind {
+      match self {
+         Self::Absolute { .. } => ImportKind::Absolute,
+         Self::Relative { .. } => ImportKind::Relative,
+         Self::Wildcard { .. } => ImportKind::Wildcard
+      }
+   }
+
+   fn is_future(&self) -> bool {
+      matches!(self, Self::Relative { from, .. } if from.is_future())
+   }
+
+   /// Returns the name of the top-level package an import is from, relative
+   /// imports have none.
+   pub fn top_level(&self) -> Option<&Token<'a>> {
+      match self {
+         Self::Absolute { modules, .. } => modules.first().and_then(|module| module.path.first()),
+         Self::Relative { from: RelativeModule::Named { level: 0, path }, .. }
+         | Self::Wildcard { from: RelativeModule::Named { level: 0, path }, .. } => path.first(),
+         _ => None
+      }
+   }
+
+   /// Returns the names bound by the import, each with the module or
+   /// attribute bound to it.
+   pub fn bindings(&self) -> Vec<(Token<'a>, String)> {
+      match self {
+         Self::Absolute { modules, .. } => modules
+            .iter()
+            .map(|module| match &module.alias {
+               Some(alias) => (alias.clone(), Module { path: module.path.clone(), alias: None }.to_string()),
+               None => (module.path[0].clone(), module.path[0].to_string_lossy().into())
+            })
+            .collect(),
+         Self::Relative { from, identifiers, .. } => identifiers
+            .iter()
+            .map(|identifier| {
+               let name = identifier.alias.as_ref().unwrap_or(&identifier.name);
+               (name.clone(), format!("{from}.{}", identifier.name.to_string_lossy()))
+            })
+            .collect(),
+         _ => vec![]
+      }
+   }
+
+   /// Puts the import in canonical form: redundant aliases and comments are
+   /// removed if configured and trailing whitespace is trimmed from the
+   /// comments.
+   pub fn normalize(&mut self, cfg: &Config) {
+      if let Self::Absolute { modules, .. } = self {
+         if cfg.remove_redundant_aliases {
+            *modules = std::mem::take(modules)
+               .into_iter()
+               .map(|module| match module.alias {
+                  Some(alias) if module.path.len() == 1 && module.path[0] == alias => Module { path: module.path, alias: None },
+                  _ => module
+               })
+               .collect();
+         }
+      }
+      if let Self::Relative { identifiers, .. } = self {
+         *identifiers = std::mem::take(identifiers)
+            .into_iter()
+            .map(|mut identifier| {
+               if cfg.remove_redundant_aliases && identifier.alias.as_ref() == Some(&identifier.name) {
+                  identifier.alias = None;
+               }
+               if cfg.trim_comments {
+                  identifier.leading.clear();
+                  identifier.comments.clear();
+               }
+               for comment in identifier.leading.iter_mut().chain(&mut identifier.comments) {
+                  comment.slice = comment.slice.trim_ascii_end();
+               }
+               identifier
+            })
+            .collect();
+      }
+      let (Self::Absolute { leading, comments, .. }
+      | Self::Relative { leading, comments, .. }
+      | Self::Wildcard { leading, comments, .. }) = self;
+      if cfg.trim_comments {
+         leading.clear();
+         comments.clear();
+      }
+      for comment in leading.iter_mut().chain(comments) {
+         comment.slice = comment.slice.trim_ascii_end();
+      }
+   }
+
+   /// Returns the position of the import in the source, which is the position
+   /// of its first token.
+   pub fn offset(&self) -> usize {
+      let tokens: Vec<&Token> = match self {
+         Self::Absolute { modules, .. } => modules.iter().flat_map(|module| module.path.iter().chain(&module.alias)).collect(),
+         Self::Relative { from, identifiers, .. } =>
+            from.path().iter().chain(identifiers.iter().flat_map(Identifier::tokens)).collect(),
+         Self::Wildcard { from, .. } => from.path().iter().collect()
+      };
+      tokens.into_iter().chain(self.leading_comments()).chain(self.comments()).map(|token| token.i).min().unwrap_or(usize::MAX)
+   }
+
+   /// Returns the trailing comments of the import, of which there are several
+   /// if imports were combined.
+   pub fn comments(&self) -> &[Token<'a>] {
+      match self {
+         Self::Absolute { comments, .. } | Self::Relative { comments, .. } | Self::Wildcard { comments, .. } => comments
+      }
+   }
+
+   pub fn comments_mut(&mut self) -> &mut CommentList<'a> {
+      match self {
+         Self::Absolute { comments, .. } | Self::Relative { comments, .. } | Self::Wildcard { comments, .. } => comments
+      }
+   }
+
+   /// Returns the comments on the lines directly above the import.
+   pub fn leading_comments(&self) -> &[Token<'a>] {
+      match self {
+         Self::Absolute { leading, .. } | Self::Relative { leading, .. } | Self::Wildcard { leading, .. } => leading
+      }
+   }
+
+   pub fn leading_comments_mut(&mut self) -> &mut CommentList<'a> {
+      match self {
+         Self::Absolute { leading, .. } | Self::Relative { leading, .. } | Self::Wildcard { leading, .. } => leading
+      }
+   }
+
+   /// Returns the first trailing comment of the import.
+   pub fn comment(&self) -> Option<&Token<'a>> {
+      self.comments().first()
+   }
+
+   /// Replaces the trailing comments of the import with `c`, if any.
+   pub fn with_comment(self, c: Option<Token<'a>>) -> Self {
+      self.with_comments(c.into_iter().collect())
+   }
+
+   /// Replaces the trailing comments of the import.
+   pub fn with_comments(mut self, c: CommentList<'a>) -> Self {
+      *self.comments_mut() = c;
+      self
+   }
+
+   /// Orders imports like `Ord`, but without placing future imports first,
+   /// with the modules forced to the top first and by their module paths
+   /// ignoring case if configured.
+   pub fn cmp_with(&self, other: &Self, cfg: &Config) -> Ordering {
+      let ordering = if cfg.future_first { self.cmp(other) } else { self.cmp_kind_and_module(other) };
+      if (cfg.future_first && self.is_future() != other.is_future()) || self.kind() != other.kind() {
+         return ordering;
+      }
+      let forced = self.forced_rank(cfg).cmp(&other.forced_rank(cfg));
+      if !cfg.sort_case_modules {
+         return forced.then(ordering);
+      }
+      let folded = match (self, other) {
+         (Self::Absolute { modules, .. }, Self::Absolute { modules: other_modules, .. }) => {
+            let fold = |modules| ordered_modules(modules, cfg).into_iter().map(|module| fold_case(&module.path)).collect();
+            let (a, b): (Vec<_>, Vec<_>) = (fold(modules), fold(other_modules));
+            a.cmp(&b)
+         },
+         (Self::Relative { from, .. }, Self::Relative { from: other_from, .. })
+         | (Self::Wildcard { from, .. }, Self::Wildcard { from: other_from, .. }) => match (from, other_from) {
+            (RelativeModule::Named { level, path }, RelativeModule::Named { level: other_level, path: other_path }) =>
+               level.cmp(other_level).then_with(|| fold_case(path).cmp(&fold_case(other_path))),
+            _ => Ordering::Equal
+         },
+         _ => Ordering::Equal
+      };
+      forced.then(folded).then(ordering)
+   }
+
+   /// Returns the position in `--force-to-top` of the module the import is
+   /// from, or of a package containing it.
+   fn forced_rank(&self, cfg: &Config) -> usize {
+      let path = match self {
+         Self::Absolute { modules, .. } => modules.first().map_or(&[][..], |module| &module.path),
+         Self::Relative { from: RelativeModule::Named { level: 0, path }, .. }
+         | Self::Wildcard { from: RelativeModule::Named { level: 0, path }, .. } => path,
+         _ => return usize::MAX
+      };
+      let path = path.iter().map(Token::to_string_lossy).join(".");
+      cfg.force_to_top
+         .iter()
+         .position(|name| path.strip_prefix(name.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('.')))
+         .unwrap_or(usize::MAX)
+   }
+}
+
+fn fold_case(path: &[Token]) -> Vec<Vec<u8>> {
+   path.iter().map(|token| token.slice.to_ascii_lowercase()).collect()
+}
+
+impl<'a> Import<'a> {
+   /// Orders absolute, relative, then wildcard imports, each by module.
+   /// Relative imports of the same module are ordered by their names.
+   fn cmp_kind_and_module(&self, other: &Self) -> Ordering {
       match self {
          Self::Absolute { modules, .. } => match other {
             Self::Absolute { modules: other_modules, .. } => modules.cmp(other_modules),
             Self::Relative { .. } => Ordering::Less,
             Self::Wildcard { .. } => Ordering::Less
          },
-         Self::Relative { from, .. } if from.is_future() => Ordering::Less,
-         Self::Relative { from, .. } => match other {
+         Self::Relative { from, identifiers, .. } => match other {
             Self::Absolute { .. } => Ordering::Greater,
-            Self::Relative { from: from2, .. } => from.cmp(from2),
+            Self::Relative { from: from2, identifiers: identifiers2, .. } =>
+               from.cmp(from2).then_with(|| identifiers.cmp(identifiers2)),
             Self::Wildcard { .. } => Ordering::Less
          },
          Self::Wildcard { from, .. } => match other {
@@ -128,48 +432,318 @@ impl<'a> Ord for Import<'a> {
    }
 }
 
+impl<'a> Ord for Import<'a> {
+   fn cmp(&self, other: &Self) -> Ordering {
+      other.is_future().cmp(&self.is_future()).then_with(|| self.cmp_kind_and_module(other))
+   }
+}
+
 impl<'a> PartialOrd for Import<'a> {
    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
       Some(self.cmp(other))
    }
 }
 
+impl<'a> PartialEq for Import<'a> {
+   fn eq(&self, other: &Self) -> bool {
+      self.cmp(other) == Ordering::Equal
+   }
+}
+
+impl<'a> Eq for Import<'a> {}
+
+impl<'a> Import<'a> {
+   /// Displays the import as configured by `cfg`.
+   pub fn display<'b>(&'b self, cfg: &'b Config) -> ImportDisplay<'a, 'b> {
+      ImportDisplay { import: self, cfg, unwrapped: false }
+   }
+}
+
+pub struct ImportDisplay<'a, 'b> {
+   import: &'b Import<'a>,
+   cfg: &'b Config,
+   unwrapped: bool
+}
+
+impl<'a, 'b> ImportDisplay<'a, 'b> {
+   /// Returns whether the line of the import is longer than `--line-length`,
+   /// measuring it unwrapped and with its comments.
+   pub fn is_too_long(&self) -> bool {
+      let (Some(length), false) = (self.cfg.line_length, self.unwrapped) else { return false };
+      let line = ImportDisplay { unwrapped: true, ..*self }.to_string();
+      line_width(line.lines().last().unwrap_or_default(), self.cfg) > length
+   }
+}
+
+/// Returns the length of a line as measured for `--line-length`.
+pub fn line_width(line: &str, cfg: &Config) -> usize {
+   if cfg.unicode_width {
+      line.width()
+   } else {
+      line.len()
+   }
+}
+
 impl<'a> Display for Import<'a> {
    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
-      match self {
-         Self::Absolute { modules, comment } => {
+      self.display(&Config::default()).fmt(f)
+   }
+}
+
+/// Displays the names of a from import, one per line inside parentheses if
+/// wrapped or any of them has comments.
+pub struct IdentifierListDisplay<'a, 'b> {
+   identifiers: &'b IdentifierList<'a>,
+   cfg: &'b Config,
+   wrap: bool
+}
+
+impl<'a, 'b> IdentifierListDisplay<'a, 'b> {
+   pub fn new(identifiers: &'b IdentifierList<'a>, cfg: &'b Config) -> Self {
+      Self { identifiers, cfg, wrap: false }
+   }
+
+   /// Prints the names one per line even if none of them has comments.
+   pub fn wrapped(self, wrap: bool) -> Self {
+      Self { wrap, ..self }
+   }
+}
+
+impl<'a, 'b> Display for IdentifierListDisplay<'a, 'b> {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+      let identifiers = ordered_identifiers(self.identifiers, self.cfg);
+      if !self.wrap && !identifiers.iter().any(|identifier| identifier.has_comments()) {
+         return write!(f, "{}", identifiers.iter().join(", "));
+      }
+      writeln!(f, "(")?;
+      for identifier in identifiers {
+         let above = if self.cfg.no_inline_comments { &identifier.comments[..] } else { &[] };
+         for comment in identifier.leading.iter().chain(above) {
+            write!(f, "{}", self.cfg.wrap_indent)?;
+            write_comment(f, comment, self.cfg)?;
+            writeln!(f)?;
+         }
+         write!(f, "{}{identifier},", self.cfg.wrap_indent)?;
+         if !self.cfg.no_inline_comments {
+            for comment in &identifier.comments {
+               write_trailing_comment(f, comment, self.cfg)?;
+            }
+         }
+         writeln!(f)?;
+      }
+      write!(f, ")")
+   }
+}
+
+fn write_comment(f: &mut std::fmt::Formatter<'_>, comment: &Token, cfg: &Config) -> fmt::Result {
+   let comment = comment.to_string_lossy();
+   let comment = comment.trim_start_matches([' ', '\t']);
+   let text = comment.trim_start_matches('#');
+   if cfg.normalize_comments && !(text.trim_start().is_empty() || text.starts_with([':', '!'])) {
+      write!(f, "{} {}", &comment[..comment.len() - text.len()], text.trim_start_matches([' ', '\t']))
+   } else {
+      write!(f, "{comment}")
+   }
+}
+
+/// Writes a comment after the code on its line, separated by the spaces
+/// written before it if configured.
+fn write_trailing_comment(f: &mut std::fmt::Formatter<'_>, comment: &Token, cfg: &Config) -> fmt::Result {
+   if cfg.preserve_comment_spacing {
+      let gap = comment.slice.iter().take_while(|c| matches!(c, b' ' | b'\t')).count();
+      write!(f, "{}", String::from_utf8_lossy(&comment.slice[..gap]))?;
+   } else {
+      write!(f, "{}", " ".repeat(cfg.comment_spacing))?;
+   }
+   write_comment(f, comment, cfg)
+}
+
+impl<'a, 'b> Display for ImportDisplay<'a, 'b> {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+      for comment in self.import.leading_comments() {
+         write_comment(f, comment, self.cfg)?;
+         writeln!(f)?;
+      }
+      let comments = self.import.comments();
+      if self.cfg.no_inline_comments {
+         for comment in comments {
+            write_comment(f, comment, self.cfg)?;
+            writeln!(f)?;
+         }
+      }
+      match self.import {
+         Import::Absolute { modules, .. } => {
             write!(f, "import ")?;
-            let mut i = modules.iter().peekable();
+            let mut i = ordered_modules(modules, self.cfg).into_iter().peekable();
             while let Some(module) = i.next() {
                write!(f, "{module}")?;
                if i.peek().is_some() {
                   write!(f, ", ")?
                }
             }
-            if let Some(comment) = comment {
-               write!(f, "  {}", str::from_utf8(comment.slice).unwrap())?;
-            }
          },
-         Self::Relative { from, identifiers, comment } => {
-            write!(f, "from {} import ", from)?;
-            let mut i = identifiers.iter().peekable();
-            while let Some(identifier) = i.next() {
-               write!(f, "{}", str::from_utf8(identifier.slice).unwrap())?;
-               if i.peek().is_some() {
-                  write!(f, ", ")?
-               }
-            }
-            if let Some(comment) = comment {
-               write!(f, "  {}", str::from_utf8(comment.slice).unwrap())?;
-            }
+         Import::Relative { from, identifiers, .. } => {
+            let identifiers = IdentifierListDisplay::new(identifiers, self.cfg).wrapped(self.is_too_long());
+            write!(f, "from {from} import {identifiers}")?;
          },
-         Self::Wildcard { from, comment } => {
+         Import::Wildcard { from, .. } => {
             write!(f, "from {} import *", from)?;
-            if let Some(comment) = comment {
-               write!(f, "  {}", str::from_utf8(comment.slice).unwrap())?;
-            }
+         }
+      }
+      if !self.cfg.no_inline_comments {
+         for comment in comments {
+            write_trailing_comment(f, comment, self.cfg)?;
          }
       }
       Ok(())
    }
 }
+
+/// Returns the names of a from import in the order they are printed.
+pub fn ordered_identifiers<'a, 'b>(identifiers: &'b IdentifierList<'a>, cfg: &Config) -> Vec<&'b Identifier<'a>> {
+   let mut identifiers: Vec<&Identifier> = identifiers.iter().collect();
+   if cfg.sort_case_identifiers {
+      identifiers.sort_by_cached_key(|identifier| identifier.name.slice.to_ascii_lowercase());
+   }
+   if cfg.order_by_type {
+      identifiers.sort_by_key(|identifier| type_rank(identifier));
+   }
+   if cfg.reverse_identifiers {
+      identifiers.reverse();
+   }
+   identifiers
+}
+
+/// Returns the modules of a plain import in the order they are printed.
+pub fn ordered_modules<'a, 'b>(modules: &'b ModuleList<'a>, cfg: &Config) -> Vec<&'b Module<'a>> {
+   let mut modules: Vec<&Module> = modules.iter().collect();
+   if cfg.sort_case_modules {
+      modules.sort_by_cached_key(|module| fold_case(&module.path));
+   }
+   modules
+}
+
+/// Ranks names as constants, then classes, then everything else, telling
+/// them apart by their casing.
+fn type_rank(identifier: &Identifier) -> u8 {
+   let name = identifier.name.slice;
+   if name.len() > 1 && name.iter().any(u8::is_ascii_uppercase) && !name.iter().any(u8::is_ascii_lowercase) {
+      0
+   } else if name.first().is_some_and(u8::is_ascii_uppercase) {
+      1
+   } else {
+      2
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::tests::Generator;
+
+   fn check_total_order<'a>(imports: &[Import<'a>], cmp: impl Fn(&Import<'a>, &Import<'a>) -> Ordering) {
+      for a in imports {
+         for b in imports {
+            let ab = cmp(a, b);
+            assert_eq!(ab, cmp(b, a).reverse(), "{a:?} and {b:?} aren't antisymmetric");
+            for c in imports {
+               if ab.is_le() && cmp(b, c).is_le() {
+                  assert!(cmp(a, c).is_le(), "{a:?} <= {b:?} <= {c:?} isn't transitive");
+               }
+            }
+         }
+      }
+   }
+
+   #[test]
+   fn imports_are_totally_ordered() {
+      let mut cfgs = vec![Config::default(), Config { future_first: false, ..Config::default() }];
+      cfgs.push(Config { sort_case_modules: true, force_to_top: vec!["b".into(), "a.A".into()], ..Config::default() });
+      cfgs.push(Config { future_first: false, sort_case_modules: true, ..Config::default() });
+      for seed in 1..=20 {
+         let mut generator = Generator(seed);
+         let imports: Vec<Import> = (0..40).map(|_| generator.import()).collect();
+         check_total_order(&imports, Import::cmp);
+         for a in &imports {
+            for b in &imports {
+               assert_eq!(a == b, a.cmp(b).is_eq(), "{a:?} and {b:?}");
+               assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+            }
+         }
+         for cfg in &cfgs {
+            check_total_order(&imports, |a, b| a.cmp_with(b, cfg));
+         }
+      }
+   }
+
+   fn parse(src: &str) -> Vec<Import<'_>> {
+      Pd::new(src).start(&mut Ps::new()).unwrap().into_iter().filter_map(Item::into_import).collect()
+   }
+
+   fn normalized(src: &str, args: &[&str]) -> String {
+      let cfg = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+      let mut imports = parse(src);
+      imports[0].normalize(&cfg);
+      imports[0].display(&cfg).to_string()
+   }
+
+   #[test]
+   fn normalize_makes_an_import_canonical() {
+      assert_eq!(normalized("from x import (c,  b,a)   #  note   \n", &[]), "from x import a, b, c  #  note");
+      assert_eq!(normalized("import os as os,  sys\t# x\t\n", &["--remove-redundant-aliases"]), "import os, sys  # x");
+      assert_eq!(normalized("from x import b as b, a as c\n", &["--remove-redundant-aliases"]), "from x import a as c, b");
+      assert_eq!(normalized("from x import b as b\n", &[]), "from x import b as b");
+      assert_eq!(normalized("from x import (b,  # about b\n a)  # x\n", &["--trim-comments"]), "from x import a, b");
+      for src in ["import a #c\n", "import a  #   c\n", "import a\t#\tc \n"] {
+         assert_eq!(normalized(src, &["--normalize-comments"]), "import a  # c");
+      }
+      assert_eq!(
+         normalized("from x import (\n    b,  #b\n    a,\n)  #   c\n", &["--normalize-comments"]),
+         "from x import (\n    a,\n    b,  # b\n)  # c"
+      );
+      assert_eq!(normalized("import a #c\n", &["--comment-spacing", "1"]), "import a #c");
+   }
+
+   #[test]
+   fn comment_of_each_kind_of_import() {
+      let imports = parse("import a  # a\nfrom b import c  # c\nfrom d import *  # d\n");
+      let comments: Vec<_> = imports.iter().map(|import| import.comment().map(Token::to_string_lossy)).collect();
+      assert_eq!(comments, [Some("  # a".into()), Some("  # c".into()), Some("  # d".into())]);
+      for import in imports {
+         let import = import.with_comment(Some(Token { slice: b"  # x", i: 0 }));
+         assert_eq!(import.comment().map(|comment| comment.slice), Some(&b"  # x"[..]));
+      }
+   }
+
+   #[test]
+   fn kind_of_each_kind_of_import() {
+      let src = "import a as b\nfrom . import c\nfrom .d import *\n# imp: off\nimport e\n# imp: on\n";
+      let items = Pd::new(src).start(&mut Ps::new()).unwrap();
+      let kinds: Vec<_> =
+         items.iter().map(|item| if let Item::Import(import) = item { Some(import.kind()) } else { None }).collect();
+      assert_eq!(kinds, [Some(ImportKind::Absolute), Some(ImportKind::Relative), Some(ImportKind::Wildcard), None]);
+   }
+
+   #[test]
+   fn comment_is_the_first_trailing_comment() {
+      let mut imports = parse("from .a import x  # x\nfrom .a import y  # y\nimport b\n");
+      crate::transformers::combine_relative_imports(&mut imports);
+      assert_eq!(imports[0].comment().map(|comment| comment.slice), Some(&b"  # x"[..]));
+      let import = imports.remove(0).with_comment(None);
+      assert_eq!(import.comment(), None);
+      let import = import.with_comment(Some(Token { slice: b"  # z", i: 0 }));
+      assert_eq!(import.to_string(), "from .a import x, y  # z");
+      assert_eq!(imports[0].comment(), None);
+   }
+
+   #[test]
+   fn imports_differing_in_comments_are_equal() {
+      let import = |comment| Import::Absolute {
+         modules: [Module { path: vec![Token { slice: b"os", i: 7 }], alias: None }].into(),
+         leading: vec![],
+         comments: vec![Token { slice: comment, i: 9 }]
+      };
+      assert_eq!(import(b"  # a"), import(b"  # b"));
+      assert!(import(b"  # a").cmp(&import(b"  # b")).is_eq());
+   }
+}
@@ -0,0 +1,415 @@
+pub mod cache;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod files;
+pub mod import;
+pub mod parser;
+pub mod timings;
+pub mod transformers;
+
+#[cfg(test)]
+mod test_dir;
+#[cfg(test)]
+mod tests;
+
+use std::fmt::Write;
+use std::io;
+use std::io::BufRead;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str;
+
+use config::*;
+use error::*;
+use import::*;
+use parser::*;
+use timings::*;
+use transformers::*;
+
+/// Sorts the imports at the top of `src`, returning the formatted source.
+pub fn sort_imports(src: &str, cfg: &Config) -> String {
+   sort_imports_in(src, &[], cfg, None)
+}
+
+/// Sorts the imports at the top of `src`, which was read from the file at
+/// `path`. Relative imports are resolved against the package of that file, or
+/// imports of that package are made relative.
+pub fn sort_imports_at(src: &str, path: &Path, cfg: &Config) -> String {
+   let package = if cfg.relative_to_absolute || cfg.absolute_to_relative { files::package(path) } else { vec![] };
+   sort_imports_in(src, &package, cfg, None)
+}
+
+/// Sorts the imports like `sort_imports_at`, adding the time spent in each
+/// phase to `timings`.
+pub fn sort_imports_timed(src: &str, path: &Path, cfg: &Config, timings: &mut Timings) -> String {
+   let package = if cfg.relative_to_absolute || cfg.absolute_to_relative { files::package(path) } else { vec![] };
+   sort_imports_in(src, &package, cfg, Some(timings))
+}
+
+fn sort_imports_in(src: &str, package: &[String], cfg: &Config, mut timings: Option<&mut Timings>) -> String {
+   // A file without code is left empty, not given a future import.
+   if src.trim().is_empty() {
+      return String::new();
+   }
+   let pd = Pd::new(src);
+   if pd.skip_file() {
+      return src.into();
+   }
+   if cfg.sort_within_parens_only {
+      return sort_within_parens(src, cfg);
+   }
+   let mut out = String::new();
+   let mut ps = Ps::new();
+   let prologue = pd.prologue(&mut ps);
+   let items = Timings::time(timings.as_deref_mut(), |t| &mut t.parse, || pd.start(&mut ps).unwrap());
+   let rest = pd.rest(&mut ps);
+   let mut segment = vec![];
+   let mut future = cfg.add_future_annotations.then(|| Pd::parse_single("from __future__ import annotations").unwrap());
+   // Each region ends the segment of imports before it, and so does the end of
+   // the imports.
+   for item in items.into_iter().map(Some).chain([None]) {
+      if let Some(Item::Import(import)) = item {
+         segment.push(import);
+         continue;
+      }
+      if cfg.remove_shadowed {
+         remove_shadowed_imports(&mut segment, rest);
+      }
+      // The future import goes before the first import or region which isn't
+      // only comments.
+      if !segment.is_empty() || item.as_ref().is_none_or(|region| !region.is_comments()) {
+         segment.extend(future.take());
+      }
+      write_segment(&mut out, &mut segment, package, cfg, timings.as_deref_mut());
+      if let Some(Item::Verbatim(source)) = item {
+         writeln!(out, "{}", source.to_string_lossy()).unwrap();
+      }
+   }
+   let mut blank_lines = cfg.blank_lines_after_imports(pd.blank_lines_before_rest(&ps));
+   if let Some((header, indent, mut imports)) = cfg.sort_type_checking.then(|| pd.type_checking(&mut ps)).flatten() {
+      if !out.is_empty() {
+         write!(out, "{}", "\n".repeat(blank_lines)).unwrap();
+      }
+      let mut block = String::new();
+      write_segment(&mut block, &mut imports, package, cfg, timings);
+      write!(out, "{header}").unwrap();
+      for line in block.lines() {
+         writeln!(out, "{indent}{line}").unwrap();
+      }
+      blank_lines = pd.blank_lines_before_rest(&ps);
+   }
+   let rest = pd.rest(&mut ps);
+   if !out.is_empty() && !rest.is_empty() {
+      write!(out, "{}", "\n".repeat(blank_lines)).unwrap();
+   }
+   if pd.newline() == "\r\n" {
+      out = out.replace("\r\n", "\n").replace('\n', "\r\n");
+   }
+   if !out.is_empty() && !prologue.is_empty() && !prologue.ends_with('\n') {
+      out.insert_str(0, pd.newline());
+   }
+   format!("{prologue}{out}{rest}")
+}
+
+/// Sorts the names inside parenthesized from imports by moving them between
+/// the positions they were written at, everything else is left as written.
+fn sort_within_parens(src: &str, cfg: &Config) -> String {
+   let pd = Pd::new(src);
+   let mut ps = Ps::new();
+   pd.prologue(&mut ps);
+   let items = pd.start(&mut ps).unwrap();
+   let mut replacements: Vec<(usize, usize, String)> = vec![];
+   for item in &items {
+      let Item::Import(import @ Import::Relative { identifiers, .. }) = item else { continue };
+      let mut positions: Vec<&Identifier> = identifiers.iter().collect();
+      positions.sort_by_key(|identifier| identifier.name.i);
+      let Some(open) = opening_parenthesis(src, positions[0].name.i) else { continue };
+      if identifiers.iter().any(Identifier::has_comments) {
+         // Names with comments are printed one per line, so the parentheses
+         // are replaced as a whole. The other comments inside them go after
+         // the closing parenthesis.
+         let tokens =
+            positions.iter().flat_map(|identifier| identifier.tokens().chain(&identifier.leading).chain(&identifier.comments));
+         let end = tokens.map(|token| token.i + token.slice.len()).max().unwrap_or(open);
+         let close = end + closing_parenthesis(&src[end..]);
+         let mut list = IdentifierListDisplay::new(identifiers, cfg).to_string().replace('\n', pd.newline());
+         for comment in import.comments().iter().filter(|comment| (open..close).contains(&comment.i)) {
+            write!(list, "{}{}", " ".repeat(cfg.comment_spacing), comment.to_string_lossy().trim_start()).unwrap();
+         }
+         replacements.push((open, close, list));
+      } else {
+         for (at, with) in positions.into_iter().zip(ordered_identifiers(identifiers, cfg)) {
+            let last = at.alias.as_ref().unwrap_or(&at.name);
+            replacements.push((at.name.i, last.i + last.slice.len(), with.to_string()));
+         }
+      }
+   }
+   replacements.sort_by_key(|(start, ..)| *start);
+   let mut out = String::new();
+   let mut i = 0;
+   for (start, end, with) in replacements {
+      write!(out, "{}{with}", &src[i..start]).unwrap();
+      i = end;
+   }
+   out + &src[i..]
+}
+
+/// Returns the position of the `(` before the name at `first`, if only
+/// whitespace and comments separate them.
+fn opening_parenthesis(src: &str, first: usize) -> Option<usize> {
+   let mut end = first;
+   loop {
+      let start = src[..end].rfind('\n').map_or(0, |i| i + 1);
+      // Import statements don't contain strings, so a `#` starts a comment.
+      let code = src[start..end].split('#').next().unwrap_or_default().trim_end();
+      if let Some(code) = code.strip_suffix('(') {
+         return Some(start + code.len());
+      }
+      if !code.trim_start().is_empty() || start == 0 {
+         return None;
+      }
+      end = start - 1;
+   }
+}
+
+/// Returns the length of the commas, whitespace and comments before a `)`,
+/// including it.
+fn closing_parenthesis(src: &str) -> usize {
+   let mut i = 0;
+   loop {
+      let rest = &src[i..];
+      if let Some(comment) = rest.strip_prefix('#') {
+         i += 1 + comment.find('\n').unwrap_or(comment.len());
+      } else if rest.starts_with([' ', '\t', '\r', '\n', ',']) {
+         i += 1;
+      } else {
+         return i + rest.starts_with(')') as usize;
+      }
+   }
+}
+
+/// Sorts the imports at the top of `src`, taking ownership of it. The result
+/// doesn't borrow from the source, so it can outlive it.
+pub fn sort_imports_owned(src: String, cfg: &Config) -> String {
+   sort_imports(&src, cfg)
+}
+
+/// Returns the first line after the imports, shortened to 60 characters.
+pub fn first_unparsed_line(src: &str) -> Option<String> {
+   let pd = Pd::new(src);
+   let mut ps = Ps::new();
+   pd.prologue(&mut ps);
+   pd.start(&mut ps).unwrap();
+   let line = pd.rest(&mut ps).lines().next()?;
+   if line.chars().count() > 60 {
+      Some(line.chars().take(57).chain("...".chars()).collect())
+   } else {
+      Some(line.into())
+   }
+}
+
+/// Returns the imports parsed from the top of `src`, pretty-printed before any
+/// transformation.
+pub fn debug_ast(src: &str) -> String {
+   let pd = Pd::new(src);
+   let mut ps = Ps::new();
+   pd.prologue(&mut ps);
+   format!("{:#?}", pd.start(&mut ps).unwrap())
+}
+
+/// Describes how the indentation of the file differs from `--wrap-indent`,
+/// judging the file by its first indented line.
+pub fn mixed_indent(src: &str, cfg: &Config) -> Option<String> {
+   let line = src.lines().find(|line| line.starts_with([' ', '\t']) && !line.trim().is_empty())?;
+   match (line.starts_with('\t'), cfg.wrap_indent.starts_with('\t')) {
+      (false, true) => Some("indented with spaces, but imports are wrapped with a tab".into()),
+      (true, false) => Some("indented with tabs, but imports are wrapped with spaces".into()),
+      _ => None
+   }
+}
+
+/// Finds names which are bound by two imports of different things.
+pub fn name_collisions(src: &str) -> Vec<String> {
+   let pd = Pd::new(src);
+   let mut ps = Ps::new();
+   pd.prologue(&mut ps);
+   let imports: Vec<Import> = pd.start(&mut ps).unwrap().into_iter().filter_map(Item::into_import).collect();
+   let mut bound: Vec<(Token, String, &Import)> = vec![];
+   let mut collisions = vec![];
+   for import in &imports {
+      for (name, target) in import.bindings() {
+         if let Some((_, _, other)) =
+            bound.iter().find(|(other_name, other_target, _)| *other_name == name && *other_target != target)
+         {
+            collisions.push(format!("`{}` is bound by both `{other}` and `{import}`", name.to_string_lossy()));
+         } else {
+            bound.push((name, target, import));
+         }
+      }
+   }
+   collisions
+}
+
+/// Sorts the imports of the file at `path`, returning whether that changed
+/// its contents. The file is only overwritten if `write` is set.
+pub fn format_file(path: &Path, cfg: &Config, write: bool) -> Result<bool, ImpError> {
+   format_file_in(path, cfg, write, None)
+}
+
+fn format_file_in(path: &Path, cfg: &Config, write: bool, timings: Option<&mut Timings>) -> Result<bool, ImpError> {
+   let src = files::read_source(path)?;
+   let formatted = match timings {
+      Some(timings) => sort_imports_timed(&src, path, cfg, timings),
+      None => sort_imports_at(&src, path, cfg)
+   };
+   let changed = formatted != src;
+   if changed && write {
+      files::write_source(path, &formatted, &src)?;
+   }
+   Ok(changed)
+}
+
+/// Sorts the imports of the files at `paths`, searching directories for
+/// Python files, and returns the files whose contents changed. Files are only
+/// overwritten if `write` is set. Files which are sorted are recorded in the
+/// cache if one is configured, and skipped by later calls until they change.
+pub fn format_paths(paths: &[PathBuf], cfg: &Config, write: bool) -> Result<Vec<PathBuf>, ImpError> {
+   format_paths_in(paths, cfg, write, None::<fn(&Path, &Timings)>)
+}
+
+/// Formats the files like `format_paths`, passing the time spent in each
+/// phase of sorting each file to `report`.
+pub fn format_paths_timed(
+   paths: &[PathBuf],
+   cfg: &Config,
+   write: bool,
+   report: impl FnMut(&Path, &Timings)
+) -> Result<Vec<PathBuf>, ImpError> {
+   format_paths_in(paths, cfg, write, Some(report))
+}
+
+fn format_paths_in(
+   paths: &[PathBuf],
+   cfg: &Config,
+   write: bool,
+   mut report: Option<impl FnMut(&Path, &Timings)>
+) -> Result<Vec<PathBuf>, ImpError> {
+   let mut cache = cfg.cache.as_deref().map(|dir| cache::Cache::load(dir, cfg));
+   let mut changed = vec![];
+   for path in files::python_files(paths)? {
+      if cache.as_ref().is_some_and(|cache| cache.is_sorted(&path)) {
+         continue;
+      }
+      let unsorted = match report.as_mut() {
+         Some(report) => {
+            let mut timings = Timings::default();
+            let unsorted = format_file_in(&path, cfg, write, Some(&mut timings))?;
+            report(&path, &timings);
+            unsorted
+         },
+         None => format_file(&path, cfg, write)?
+      };
+      if let Some(cache) = cache.as_mut().filter(|_| !unsorted || write) {
+         cache.insert(&path)?;
+      }
+      if unsorted {
+         changed.push(path);
+      }
+   }
+   if let Some(cache) = cache {
+      cache.save()?;
+   }
+   Ok(changed)
+}
+
+/// Shows the changes to each file and asks whether to write them, reading the
+/// answers from `input`. Files are left unchanged unless the answer is yes,
+/// returns the files which were written.
+pub fn format_interactive(
+   paths: &[PathBuf],
+   cfg: &Config,
+   input: &mut impl BufRead,
+   output: &mut impl io::Write
+) -> Result<Vec<PathBuf>, ImpError> {
+   let mut written = vec![];
+   for path in paths {
+      let src = files::read_source(path)?;
+      let formatted = sort_imports_at(&src, path, cfg);
+      if formatted == src {
+         continue;
+      }
+      let prompt =
+         format!("{}Apply changes to {}? [y/N] ", diff::diff(&path.to_string_lossy(), &src, &formatted), path.display());
+      output.write_all(prompt.as_bytes()).and_then(|_| output.flush()).map_err(|err| ImpError::Io(path.clone(), err))?;
+      let mut answer = String::new();
+      input.read_line(&mut answer).map_err(|err| ImpError::Io(path.clone(), err))?;
+      if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+         files::write_source(path, &formatted, &src)?;
+         written.push(path.clone());
+      }
+   }
+   Ok(written)
+}
+
+/// Sorts and writes the imports found between two `# fmt: off` regions.
+fn write_segment<'a>(
+   out: &mut String,
+   imports: &mut Vec<Import<'a>>,
+   package: &'a [String],
+   cfg: &Config,
+   mut timings: Option<&mut Timings>
+) {
+   for import in imports.iter_mut() {
+      import.normalize(cfg);
+   }
+   if cfg.relative_to_absolute {
+      relative_to_absolute(imports, package);
+   }
+   if cfg.absolute_to_relative {
+      absolute_to_relative(imports, package);
+   }
+   if cfg.split_noqa {
+      directives_to_names(imports);
+   }
+   if cfg.combine_from_imports {
+      Timings::time(timings.as_deref_mut(), |t| &mut t.combine, || combine_relative_imports(imports));
+   }
+   if cfg.separate_imports {
+      Timings::time(timings.as_deref_mut(), |t| &mut t.separate, || separate_absolute_imports(imports));
+   } else if cfg.line_length.is_some() {
+      // Plain imports can't be wrapped, so the ones which are too long are
+      // separated anyway.
+      let (mut long, short): (Vec<Import>, Vec<Import>) = std::mem::take(imports)
+         .into_iter()
+         .partition(|import| matches!(import, Import::Absolute { .. }) && import.display(cfg).is_too_long());
+      *imports = short;
+      separate_absolute_imports(&mut long);
+      imports.append(&mut long);
+   }
+   if cfg.split_noqa {
+      split_directive_names(imports);
+   }
+   if cfg.no_wrap {
+      unwrap_identifier_comments(imports);
+   }
+   Timings::time(
+      timings.as_deref_mut(),
+      |t| &mut t.sort,
+      || {
+         imports.sort_by(|a, b| a.cmp_with(b, cfg).then(a.offset().cmp(&b.offset())));
+         if cfg.group_from_imports {
+            group_from_imports_with_absolute(imports);
+         }
+      }
+   );
+   Timings::time(
+      timings,
+      |t| &mut t.render,
+      || {
+         for i in imports.drain(..) {
+            writeln!(out, "{}", i.display(cfg)).unwrap();
+         }
+      }
+   );
+}
@@ -1,32 +1,187 @@
-mod import;
-mod parser;
-mod transformers;
-
 use std::env::args;
-use std::fs::read_to_string;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Read;
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process::exit;
 
-use parser::*;
-use transformers::*;
+use imp::config::*;
+use imp::debug_ast;
+use imp::error::*;
+use imp::files::*;
+use imp::first_unparsed_line;
+use imp::format_interactive;
+use imp::format_paths;
+use imp::format_paths_timed;
+use imp::mixed_indent;
+use imp::name_collisions;
+use imp::sort_imports;
+use imp::sort_imports_at;
+use imp::sort_imports_timed;
+use imp::timings::*;
 
 fn main() {
-   let mut args = args();
-   args.next();
-   let path = args.next().unwrap_or("/dev/stdin".into());
-   let path = Path::new(path.as_str());
-   let src = read_to_string(path).unwrap();
-   let pd = Pd::new(src.as_str());
-   let mut ps = Ps::new();
-   let mut imports = pd.start(&mut ps).unwrap();
-   let required_pd = Pd::new(r#"from __future__ import annotations"#);
-   let mut required_ps = Ps::new();
-   let required_imports = required_pd.start(&mut required_ps).unwrap();
-   imports.extend(required_imports);
-   combine_relative_imports(&mut imports);
-   separate_absolute_imports(&mut imports);
-   imports.sort();
-   for i in imports {
-      println!("{i}");
-   }
-   print!("\n\n{}", pd.rest(&mut ps));
+   let code = Config::from_args(args().skip(1))
+      .and_then(|cfg| {
+         cfg.validate()?;
+         run(&cfg)
+      })
+      .unwrap_or_else(|err| {
+         eprintln!("imp: {err}");
+         2
+      });
+   exit(code);
+}
+
+fn run(cfg: &Config) -> Result<i32, ImpError> {
+   if let Some(code) = &cfg.code {
+      print!("{}", sort_imports(code, cfg));
+      return Ok(0);
+   }
+   if cfg.paths.is_empty() {
+      return stdin(cfg);
+   }
+   let paths = python_files(&cfg.paths)?;
+   if cfg.check {
+      return check(&paths, cfg);
+   }
+   if cfg.interactive {
+      let stdin = io::stdin();
+      if stdin.is_terminal() {
+         format_interactive(&paths, cfg, &mut stdin.lock(), &mut io::stderr())?;
+      } else {
+         format_interactive(&paths, cfg, &mut io::empty(), &mut io::stderr())?;
+      }
+      return Ok(0);
+   }
+   if cfg.in_place {
+      return in_place(&paths, cfg);
+   }
+   for path in paths {
+      let src = read_source(&path)?;
+      diagnose(&path, &src, cfg);
+      print(&sort_source(&src, &path, cfg), &src)?;
+   }
+   Ok(0)
+}
+
+/// Sorts the source piped to stdin, or checks it if `--check` is set. It is
+/// named by `--stdin-filename` in messages.
+fn stdin(cfg: &Config) -> Result<i32, ImpError> {
+   let path = cfg.stdin_filename.clone().unwrap_or_else(|| PathBuf::from("-"));
+   let mut src = vec![];
+   let src = io::stdin().read_to_end(&mut src).and_then(|_| decode(src)).map_err(|err| ImpError::Io(path.clone(), err))?;
+   diagnose(&path, &src, cfg);
+   let formatted = sort_source(&src, &path, cfg);
+   if cfg.check {
+      let unsorted = if formatted == src { vec![] } else { vec![path] };
+      return Ok(report(1, &unsorted, cfg));
+   }
+   print(&formatted, &src)?;
+   Ok(0)
+}
+
+/// Prints source formatted from `original` in the encoding `original`
+/// declares.
+fn print(src: &str, original: &str) -> Result<(), ImpError> {
+   io::stdout().write_all(&encode(src, original)).map_err(|err| ImpError::Io("-".into(), err))
+}
+
+/// Sorts the imports of `src`, printing the time spent in each phase if
+/// verbose.
+fn sort_source(src: &str, path: &Path, cfg: &Config) -> String {
+   if !cfg.verbose {
+      return sort_imports_at(src, path, cfg);
+   }
+   let mut timings = Timings::default();
+   let formatted = sort_imports_timed(src, path, cfg, &mut timings);
+   eprintln!("{}: {timings}", path.display());
+   formatted
+}
+
+/// Formats the files like `format_paths`, printing the time spent in each
+/// phase of sorting each file if verbose.
+fn format(paths: &[PathBuf], cfg: &Config, write: bool) -> Result<Vec<PathBuf>, ImpError> {
+   if !cfg.verbose {
+      return format_paths(paths, cfg, write);
+   }
+   format_paths_timed(paths, cfg, write, |path, timings| eprintln!("{}: {timings}", path.display()))
+}
+
+/// Sorts the files in place, listing the ones which changed if verbose.
+fn in_place(paths: &[PathBuf], cfg: &Config) -> Result<i32, ImpError> {
+   for path in paths {
+      diagnose_file(path, cfg)?;
+   }
+   let formatted = format(paths, cfg, true)?;
+   if cfg.verbose {
+      for path in &formatted {
+         eprintln!("formatted {}", path.display());
+      }
+      eprintln!("{} {} formatted.", formatted.len(), plural(formatted.len(), "file", "files"));
+   }
+   Ok(0)
+}
+
+/// Reports the files which are not sorted, exiting with `1` if there are any.
+fn check(paths: &[PathBuf], cfg: &Config) -> Result<i32, ImpError> {
+   for path in paths {
+      diagnose_file(path, cfg)?;
+   }
+   let unsorted = format(paths, cfg, false)?;
+   Ok(report(paths.len(), &unsorted, cfg))
+}
+
+/// Prints the `--check` report for `checked` files of which `unsorted` are not
+/// sorted, returning the exit code.
+fn report(checked: usize, unsorted: &[PathBuf], cfg: &Config) -> i32 {
+   if !cfg.quiet {
+      for path in unsorted {
+         eprintln!("would reformat {}", path.display());
+      }
+      match unsorted.len() {
+         0 => eprintln!("All {checked} {} correctly sorted.", plural(checked, "file is", "files are")),
+         n => eprintln!("{n} {} be reformatted.", plural(n, "file would", "files would"))
+      }
+   }
+   !unsorted.is_empty() as i32
+}
+
+/// Prints the warnings and verbose information about a file.
+fn diagnose(path: &Path, src: &str, cfg: &Config) {
+   if cfg.warn_collisions {
+      for collision in name_collisions(src) {
+         eprintln!("{}: {collision}", path.display());
+      }
+   }
+   if cfg.warn_mixed_indent {
+      if let Some(warning) = mixed_indent(src, cfg) {
+         eprintln!("{}: {warning}", path.display());
+      }
+   }
+   if cfg.debug_ast {
+      eprintln!("{}: {}", path.display(), debug_ast(src));
+   }
+   if cfg.verbose {
+      if let Some(line) = first_unparsed_line(src) {
+         eprintln!("{}: stopped at: {line}", path.display());
+      }
+   }
+}
+
+fn diagnose_file(path: &Path, cfg: &Config) -> Result<(), ImpError> {
+   if cfg.warn_collisions || cfg.warn_mixed_indent || cfg.debug_ast || cfg.verbose {
+      diagnose(path, &read_source(path)?, cfg);
+   }
+   Ok(())
+}
+
+fn plural<'a>(n: usize, one: &'a str, many: &'a str) -> &'a str {
+   if n == 1 {
+      one
+   } else {
+      many
+   }
 }
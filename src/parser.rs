@@ -1,23 +1,40 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::str;
 
+use unicode_xid::UnicodeXID;
+
 use crate::import::*;
 
-#[derive(Eq, PartialOrd, Clone)]
+#[derive(Eq, Clone)]
 pub struct Token<'a> {
    pub slice: &'a [u8],
    pub i: usize
 }
 
+impl<'a> Token<'a> {
+   /// Returns the text of the token, replacing invalid UTF-8 so that printing
+   /// it can't fail.
+   pub fn to_string_lossy(&self) -> Cow<'a, str> {
+      String::from_utf8_lossy(self.slice)
+   }
+}
+
 impl<'a> Ord for Token<'a> {
    fn cmp(&self, other: &Self) -> Ordering {
       self.slice.cmp(other.slice)
    }
 }
 
+impl<'a> PartialOrd for Token<'a> {
+   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+      Some(self.cmp(other))
+   }
+}
+
 impl<'a> PartialEq for Token<'a> {
    fn eq(&self, other: &Self) -> bool {
       self.slice == other.slice
@@ -32,7 +49,44 @@ impl<'a> Hash for Token<'a> {
 
 impl<'a> Debug for Token<'a> {
    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
-      write!(f, "{:#?}", str::from_utf8(self.slice).unwrap())
+      write!(f, "{:#?}", self.to_string_lossy())
+   }
+}
+
+/// An import at the top of the file, or a region of the source emitted as
+/// written: one between `# fmt: off` and `# fmt: on`, an import marked with
+/// `# imp: skip`, or comment lines followed by a blank line.
+#[derive(Clone)]
+pub enum Item<'a> {
+   Import(Import<'a>),
+   Verbatim(Token<'a>)
+}
+
+impl<'a> Item<'a> {
+   /// Returns the import, regions kept as written have none.
+   pub fn into_import(self) -> Option<Import<'a>> {
+      match self {
+         Self::Import(import) => Some(import),
+         Self::Verbatim(_) => None
+      }
+   }
+
+   /// Returns whether the item is a region made only of comments, which
+   /// doesn't have to follow the `from __future__` imports.
+   pub fn is_comments(&self) -> bool {
+      matches!(self, Self::Verbatim(source) if source.slice.split(|c| *c == b'\n').all(|line| {
+         let line = line.trim_ascii();
+         line.is_empty() || line.starts_with(b"#")
+      }))
+   }
+}
+
+impl<'a> Debug for Item<'a> {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+         Self::Import(import) => import.fmt(f),
+         Self::Verbatim(source) => f.debug_tuple("Verbatim").field(source).finish()
+      }
    }
 }
 
@@ -41,23 +95,42 @@ pub struct Pd<'a> {
    src: &'a [u8]
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Ps {
    i: usize,
-   rest: usize
+   rest: usize,
+   /// Indentation of the imports being parsed, which is `0` except inside an
+   /// `if TYPE_CHECKING:` block.
+   indent: usize,
+   /// End of the source printed as written so far, including the line break
+   /// printed after a region. A comment block doesn't take the blank lines
+   /// before this again.
+   printed: usize,
+   /// Range of comment and blank lines last scanned for imports after them,
+   /// and whether there were any, so that the comment blocks in the range
+   /// aren't scanned again.
+   lookahead: Option<(usize, usize, bool)>
 }
 
 impl Ps {
    pub fn new() -> Self {
-      Self { i: 0, rest: 0 }
+      Self { i: 0, rest: 0, indent: 0, printed: 0, lookahead: None }
    }
 }
 
-impl<'b> Pd<'b> {
-   pub fn new(src: &'b str) -> Self {
+impl<'a> Pd<'a> {
+   pub fn new(src: &'a str) -> Self {
       Self { src: src.as_bytes() }
    }
 
+   /// Parses `src` as exactly one import statement followed by whitespace.
+   pub fn parse_single(src: &'a str) -> Option<Import<'a>> {
+      let pd = Self::new(src);
+      let mut s = Ps::new();
+      let import = pd.import(&mut s)?;
+      (s.i == pd.src.len()).then_some(import)
+   }
+
    fn backtrack<T, F>(&self, s: &mut Ps, f: F) -> Option<T>
    where F: Fn(&mut Ps) -> Option<T> {
       let i = s.i;
@@ -81,17 +154,16 @@ impl<'b> Pd<'b> {
       true
    }
 
-   fn identifier<'a>(&'a self, s: &mut Ps) -> Option<Token<'a>> {
+   /// Parses an identifier as defined by PEP 3131, which starts with `_` or a
+   /// character with the XID_Start property followed by XID_Continue
+   /// characters.
+   fn identifier(&self, s: &mut Ps) -> Option<Token<'a>> {
       let mut i = 0;
-      loop {
-         if self.src.len() <= (s.i + i) {
+      while let Some(c) = self.char_at(s.i + i) {
+         if !(c == '_' || if i == 0 { c.is_xid_start() } else { c.is_xid_continue() }) {
             break;
          }
-         let c = self.src[s.i + i];
-         if !(c == b'_' || c.is_ascii_alphanumeric()) {
-            break;
-         }
-         i += 1;
+         i += c.len_utf8();
       }
       if i == 0 {
          return None;
@@ -100,31 +172,142 @@ impl<'b> Pd<'b> {
       Some(Token { slice: &self.src[(s.i - i)..s.i], i: s.i - i })
    }
 
-   fn comment<'a>(&'a self, s: &mut Ps) -> Option<Token<'a>> {
+   /// Decodes the UTF-8 character starting at `i`.
+   fn char_at(&self, i: usize) -> Option<char> {
+      let len = match *self.src.get(i)? {
+         c if c < 0x80 => 1,
+         c if c >= 0xf0 => 4,
+         c if c >= 0xe0 => 3,
+         _ => 2
+      };
+      str::from_utf8(self.src.get(i..i + len)?).ok()?.chars().next()
+   }
+
+   fn comment(&self, s: &mut Ps) -> Option<Token<'a>> {
       if !(self.src.len() > s.i && self.src[s.i] == b'#') {
          return None;
       }
       let start = s.i;
-      while self.src.len() > s.i && self.src[s.i] != b'\n' {
+      while self.src.len() > s.i && !matches!(self.src[s.i], b'\r' | b'\n') {
          s.i += 1;
       }
+      let end = s.i;
       if self.src.len() > s.i {
          s.i += 1;
       }
-      Some(Token { slice: &self.src[start..s.i - 1], i: start })
+      Some(Token { slice: &self.src[start..end], i: start })
+   }
+
+   /// Parses a comment on the same line as the preceding token, including the
+   /// spaces between them.
+   fn trailing_comment(&self, s: &mut Ps) -> Option<Token<'a>> {
+      if self.starts_line(s.i) {
+         return None;
+      }
+      let gap = self.src[..s.i].iter().rev().take_while(|c| matches!(c, b' ' | b'\t')).count();
+      let comment = self.comment(s)?;
+      let start = comment.i - gap;
+      Some(Token { slice: &self.src[start..comment.i + comment.slice.len()], i: start })
+   }
+
+   /// Returns whether the statement ends at the current position, which must be
+   /// followed by a newline, a `;`, a comment or the end of the file. Anything
+   /// else, such as a stray `\`, means the statement is not a plain import.
+   fn end_of_statement(&self, s: &Ps) -> bool {
+      s.i == self.src.len() || matches!(self.src[s.i], b'#' | b';') || self.starts_line(s.i)
+   }
+
+   /// Parses the end of an import statement and its trailing comment, which
+   /// belongs to the last statement on the line if they are separated by `;`.
+   fn statement_end(&self, s: &mut Ps) -> Option<CommentList<'a>> {
+      self.whitespace(s);
+      if !self.end_of_statement(s) {
+         return None;
+      }
+      if self.string(s, ";") {
+         self.inline_whitespace(s);
+      }
+      let comments = self.trailing_comment(s).into_iter().collect();
+      self.whitespace(s);
+      Some(comments)
    }
 
+   /// Returns the indentation of the line of position `i`, if nothing else
+   /// precedes `i` on its line.
+   fn indentation(&self, i: usize) -> Option<usize> {
+      let indent = self.src[..i].iter().rev().take_while(|c| matches!(c, b' ' | b'\t')).count();
+      (indent == i || self.src[i - indent - 1] == b'\n').then_some(indent)
+   }
+
+   /// Returns whether only whitespace separates position `i` from the end of
+   /// the previous line, newlines escaped by a `\` don't end a line.
+   fn starts_line(&self, mut i: usize) -> bool {
+      while i > 0 {
+         match self.src[i - 1] {
+            b' ' | b'\t' | b'\r' => i -= 1,
+            b'\n' => {
+               let line = &self.src[..i - 1];
+               let line = line.strip_suffix(b"\r").unwrap_or(line);
+               if !line.ends_with(b"\\") {
+                  return true;
+               }
+               i = line.len() - 1;
+            },
+            _ => return false
+         }
+      }
+      false
+   }
+
+   /// Skips a `\` at the end of a line, which continues the statement on the
+   /// next line.
+   fn line_continuation(&self, s: &mut Ps) -> bool {
+      self.string(s, "\\\n") || self.string(s, "\\\r\n")
+   }
+
+   /// Skips spaces, tabs and newlines. Python rejects other whitespace such as
+   /// U+00A0 outside of strings and comments, so it is not skipped and the
+   /// statement containing it is left in the rest of the file.
    fn whitespace(&self, s: &mut Ps) {
-      while self.src.len() > s.i {
-         let c = self.src[s.i];
-         if !(c == b' ' || c == b'\n') {
+      loop {
+         if self.line_continuation(s) {
+            continue;
+         }
+         if !self.src.get(s.i).is_some_and(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n')) {
             break;
          }
          s.i += 1;
       }
    }
 
-   fn module_path<'a>(&'a self, s: &mut Ps) -> Option<ModulePath<'a>> {
+   /// Skips whitespace without leaving the current line, lines continued by a
+   /// `\` are part of it.
+   fn inline_whitespace(&self, s: &mut Ps) {
+      loop {
+         if self.line_continuation(s) {
+            continue;
+         }
+         if !self.src.get(s.i).is_some_and(|c| matches!(c, b' ' | b'\t')) {
+            break;
+         }
+         s.i += 1;
+      }
+   }
+
+   /// Parses a keyword, which must not be followed by more of an identifier.
+   fn keyword(&self, s: &mut Ps, keyword: &str) -> bool {
+      let i = s.i;
+      if !self.string(s, keyword) {
+         return false;
+      }
+      if self.char_at(s.i).is_some_and(|c| c == '_' || c.is_xid_continue()) {
+         s.i = i;
+         return false;
+      }
+      true
+   }
+
+   fn module_path(&self, s: &mut Ps) -> Option<ModulePath<'a>> {
       self.backtrack(s, |s| {
          let mut path = ModulePath::new();
          while let Some(identifier) = self.identifier(s) {
@@ -142,11 +325,11 @@ impl<'b> Pd<'b> {
       })
    }
 
-   fn module<'a>(&'a self, s: &mut Ps) -> Option<Module<'a>> {
+   fn module(&self, s: &mut Ps) -> Option<Module<'a>> {
       self.backtrack(s, |s| {
          let path = self.module_path(s)?;
          self.whitespace(s);
-         if self.string(s, "as") {
+         if self.keyword(s, "as") {
             self.whitespace(s);
             Some(Module { path, alias: Some(self.identifier(s)?) })
          } else {
@@ -155,10 +338,10 @@ impl<'b> Pd<'b> {
       })
    }
 
-   fn module_list<'a>(&'a self, s: &mut Ps) -> Option<ModuleList<'a>> {
+   /// Parses the modules of a plain import, which can't be parenthesized.
+   fn module_list(&self, s: &mut Ps) -> Option<ModuleList<'a>> {
       self.backtrack(s, |s| {
          let mut modules = ModuleList::new();
-         self.string(s, "(");
          loop {
             let module = self.module(s)?;
             self.whitespace(s);
@@ -168,34 +351,84 @@ impl<'b> Pd<'b> {
             }
             self.whitespace(s);
          }
-         if self.string(s, ")") {
+         (!modules.is_empty()).then_some(modules)
+      })
+   }
+
+   fn aliased_identifier(&self, s: &mut Ps) -> Option<Identifier<'a>> {
+      self.backtrack(s, |s| {
+         let name = self.identifier(s)?;
+         self.whitespace(s);
+         if self.keyword(s, "as") {
             self.whitespace(s);
+            Some(Identifier { name, alias: Some(self.identifier(s)?), leading: vec![], comments: vec![] })
+         } else {
+            Some(Identifier { name, alias: None, leading: vec![], comments: vec![] })
          }
-         (!modules.is_empty()).then_some(modules)
       })
    }
 
-   fn identifier_list<'a>(&'a self, s: &mut Ps) -> Option<IdentifierList<'a>> {
+   /// Parses the names of a from import. Inside parentheses, comments on the
+   /// lines above a name and after it belong to that name, the others are
+   /// returned as comments of the import.
+   fn identifier_list(&self, s: &mut Ps) -> Option<(IdentifierList<'a>, CommentList<'a>)> {
       self.backtrack(s, |s| {
          let mut identifiers = IdentifierList::new();
-         self.string(s, "(");
+         let mut comments = vec![];
+         let mut leading = vec![];
+         let parenthesized = self.string(s, "(");
+         if parenthesized {
+            self.inline_whitespace(s);
+            comments.extend(self.trailing_comment(s));
+            leading = self.comment_lines(s);
+         }
          loop {
-            let identifier = self.identifier(s)?;
+            let mut identifier = self.aliased_identifier(s)?;
+            identifier.leading = std::mem::take(&mut leading);
             self.whitespace(s);
-            identifiers.insert(identifier);
-            if !self.string(s, ",") {
+            let comma = self.string(s, ",");
+            if parenthesized {
+               self.inline_whitespace(s);
+               identifier.comments.extend(self.trailing_comment(s));
+               leading = self.comment_lines(s);
+            }
+            insert_identifier(&mut identifiers, identifier);
+            if parenthesized && (!comma || self.src.get(s.i) == Some(&b')')) {
+               comments.append(&mut leading);
                break;
             }
-            self.whitespace(s);
+            if !comma {
+               break;
+            }
+            if !parenthesized {
+               // A trailing comma ends the list if nothing follows it on the line.
+               self.inline_whitespace(s);
+               if self.identifier(&mut s.clone()).is_none() {
+                  break;
+               }
+            }
          }
-         if self.string(s, ")") {
+         if parenthesized {
+            if !self.string(s, ")") {
+               return None;
+            }
             self.whitespace(s);
          }
-         (!identifiers.is_empty()).then_some(identifiers)
+         (!identifiers.is_empty()).then_some((identifiers, comments))
       })
    }
 
-   fn relative_module<'a>(&'a self, s: &mut Ps) -> Option<RelativeModule<'a>> {
+   /// Parses the comments on their own lines before the next token.
+   fn comment_lines(&self, s: &mut Ps) -> CommentList<'a> {
+      let mut comments = vec![];
+      loop {
+         self.whitespace(s);
+         let Some(comment) = self.comment(s) else { return comments };
+         comments.push(comment);
+      }
+   }
+
+   fn relative_module(&self, s: &mut Ps) -> Option<RelativeModule<'a>> {
       self.backtrack(s, |s| {
          let mut level = 0;
          while self.string(s, ".") {
@@ -211,34 +444,37 @@ impl<'b> Pd<'b> {
       })
    }
 
-   fn import<'a>(&'a self, s: &mut Ps) -> Option<Import<'a>> {
+   fn import(&self, s: &mut Ps) -> Option<Import<'a>> {
       self.backtrack(s, |s| {
+         // Imports indented differently are inside another block.
+         if self.indentation(s.i).is_some_and(|indent| indent != s.indent) {
+            return None;
+         }
          if self.string(s, "import") {
             self.whitespace(s);
             let modules = self.module_list(s)?;
-            self.whitespace(s);
-            let comment = self.comment(s);
-            self.whitespace(s);
-            Some(Import::Absolute { modules, comment })
+            let comments = self.statement_end(s)?;
+            Some(Import::Absolute { modules, leading: vec![], comments })
          } else if self.string(s, "from") {
             self.whitespace(s);
             let from = self.relative_module(s)?;
             self.whitespace(s);
+            // A comment between the module and `import` is kept with the
+            // trailing comment.
+            let mut comments: CommentList = self.trailing_comment(s).into_iter().collect();
+            self.whitespace(s);
             if !self.string(s, "import") {
                return None;
             }
             self.whitespace(s);
             if self.string(s, "*") {
-               self.whitespace(s);
-               let comment = self.comment(s);
-               self.whitespace(s);
-               Some(Import::Wildcard { from, comment })
+               comments.extend(self.statement_end(s)?);
+               Some(Import::Wildcard { from, leading: vec![], comments })
             } else {
-               let identifiers = self.identifier_list(s)?;
-               self.whitespace(s);
-               let comment = self.comment(s);
-               self.whitespace(s);
-               Some(Import::Relative { from, identifiers, comment })
+               let (identifiers, list_comments) = self.identifier_list(s)?;
+               comments.extend(list_comments);
+               comments.extend(self.statement_end(s)?);
+               Some(Import::Relative { from, identifiers, leading: vec![], comments })
             }
          } else {
             None
@@ -246,18 +482,391 @@ impl<'b> Pd<'b> {
       })
    }
 
-   pub fn start<'a>(&'a self, s: &mut Ps) -> Option<Vec<Import<'a>>> {
+   /// Parses an import with the comments on the lines directly above it. The
+   /// comments are left alone if a blank line or a `# fmt: off` follows them.
+   fn commented_import(&self, s: &mut Ps) -> Option<Import<'a>> {
+      self.backtrack(s, |s| {
+         let mut leading = vec![];
+         while self.region_start(&mut s.clone()).is_none() {
+            let Some(comment) = self.comment(s) else { break };
+            if self.src[s.i - 1] == b'\r' {
+               self.string(s, "\n");
+            }
+            self.inline_whitespace(s);
+            if self.src.get(s.i).is_some_and(|c| matches!(c, b'\r' | b'\n')) {
+               return None;
+            }
+            leading.push(comment);
+         }
+         let mut import = self.import(s)?;
+         *import.leading_comments_mut() = leading;
+         Some(import)
+      })
+   }
+
+   /// Parses comment lines followed by a blank line, which are kept in place as
+   /// written along with the blank lines before them if more imports follow.
+   fn comment_block(&self, s: &mut Ps) -> Option<Item<'a>> {
+      self.backtrack(s, |s| {
+         let mut start = s.i;
+         while start > s.printed && matches!(self.src[start - 1], b' ' | b'\t' | b'\r' | b'\n') {
+            start -= 1;
+         }
+         if start > s.printed {
+            start += self.src[start..s.i].iter().position(|c| *c == b'\n').map_or(0, |i| i + 1);
+         }
+         loop {
+            if self.region_start(&mut s.clone()).is_some() {
+               return None;
+            }
+            self.comment(s)?;
+            if self.src[s.i - 1] == b'\r' {
+               self.string(s, "\n");
+            }
+            let end = s.i;
+            self.inline_whitespace(s);
+            if self.src.get(s.i).is_some_and(|c| matches!(c, b'\r' | b'\n')) {
+               self.whitespace(s);
+               let imports_follow = self.imports_follow(s);
+               s.i = end;
+               return imports_follow.then_some(Item::Verbatim(Token { slice: &self.src[start..end], i: start }));
+            }
+         }
+      })
+   }
+
+   /// Returns whether an import or a region left as written follows the
+   /// comment and blank lines at the current position, without advancing.
+   fn imports_follow(&self, s: &mut Ps) -> bool {
+      if let Some((start, end, follow)) = s.lookahead {
+         if (start..=end).contains(&s.i) {
+            return follow;
+         }
+      }
+      let mut next = s.clone();
+      let follow = loop {
+         self.whitespace(&mut next);
+         if self.peek_import(&next) || self.verbatim(&mut next.clone()).is_some() {
+            break true;
+         }
+         if self.region_start(&mut next.clone()).is_some() || self.comment(&mut next).is_none() {
+            break false;
+         }
+      };
+      s.lookahead = Some((s.i, next.i, follow));
+      follow
+   }
+
+   /// Returns whether an import starts at the current position, without
+   /// advancing.
+   pub fn peek_import(&self, s: &Ps) -> bool {
+      self.import(&mut s.clone()).is_some()
+   }
+
+   /// Parses a comment which reads `# <marker>`.
+   fn marker(&self, s: &mut Ps, marker: &str) -> Option<usize> {
+      self.backtrack(s, |s| {
+         let comment = self.comment(s)?;
+         is_marker(&comment, marker).then_some(comment.i + comment.slice.len())
+      })
+   }
+
+   /// Parses an import with a `# imp: skip` comment after it or after one of
+   /// its names, which is kept in place as written along with the comments
+   /// above it.
+   fn skipped_import(&self, s: &mut Ps) -> Option<Item<'a>> {
+      self.backtrack(s, |s| {
+         let start = s.i;
+         let import = self.commented_import(s)?;
+         let name_comments = match &import {
+            Import::Relative { identifiers, .. } => identifiers.iter().flat_map(|identifier| &identifier.comments).collect(),
+            _ => vec![]
+         };
+         if !import.comments().iter().chain(name_comments).any(|comment| is_marker(comment, "imp:skip")) {
+            return None;
+         }
+         // The statement ends before the whitespace after it, which may
+         // include lines continued by a `\`.
+         let mut end = s.i;
+         while end > start
+            && (matches!(self.src[end - 1], b' ' | b'\t' | b'\r' | b'\n')
+               || self.src[end - 1] == b'\\' && matches!(self.src.get(end), Some(b'\r' | b'\n')))
+         {
+            end -= 1;
+         }
+         Some(Item::Verbatim(Token { slice: &self.src[start..end], i: start }))
+      })
+   }
+
+   /// Parses a comment which starts a region left as written, returning the
+   /// marker which ends it.
+   fn region_start(&self, s: &mut Ps) -> Option<&'static str> {
+      [("fmt:off", "fmt:on"), ("imp:off", "imp:on")].into_iter().find_map(|(off, on)| self.marker(s, off).map(|_| on))
+   }
+
+   /// Parses a region between `# fmt: off` and `# fmt: on` comments, or
+   /// `# imp: off` and `# imp: on`, which must be left as written.
+   fn verbatim(&self, s: &mut Ps) -> Option<Item<'a>> {
+      self.backtrack(s, |s| {
+         let start = s.i;
+         let on = self.region_start(s)?;
+         loop {
+            self.whitespace(s);
+            if self.src.len() <= s.i {
+               return None;
+            }
+            if let Some(end) = self.marker(s, on) {
+               return Some(Item::Verbatim(Token { slice: &self.src[start..end], i: start }));
+            }
+            while self.src.len() > s.i && self.src[s.i] != b'\n' {
+               s.i += 1;
+            }
+         }
+      })
+   }
+
+   /// Parses the lines which must stay at the top of the file, a shebang, an
+   /// encoding declaration in the first two lines and the module docstring
+   /// with the comments above it, each with the blank lines after it.
+   pub fn prologue(&self, s: &mut Ps) -> &'a str {
+      let start = s.i;
+      if start != 0 {
+         return "";
+      }
+      if self.src.starts_with(b"#!") {
+         self.comment(s);
+         self.blank_lines(s);
+      }
+      let line = self.src[..s.i].iter().filter(|c| **c == b'\n').count();
+      let coding = |s: &mut Ps| self.comment(s).filter(|comment| is_encoding_declaration(comment.slice));
+      if line < 2 && self.backtrack(s, coding).is_some() {
+         self.blank_lines(s);
+      }
+      let docstring = self.backtrack(s, |s| {
+         self.blank_lines(s);
+         // Comments above the docstring, such as a license, stay above it.
+         while self.comment(s).is_some() {
+            self.blank_lines(s);
+         }
+         self.docstring(s)
+      });
+      if docstring.is_some() {
+         self.blank_lines(s);
+      }
+      s.rest = s.i;
+      s.printed = s.i;
+      str::from_utf8(&self.src[start..s.i]).unwrap()
+   }
+
+   /// Returns whether a `# imp: skip_file` comment is among the comment lines
+   /// at the top of the file.
+   pub fn skip_file(&self) -> bool {
+      let mut s = Ps::new();
+      loop {
+         self.whitespace(&mut s);
+         let Some(comment) = self.comment(&mut s) else { return false };
+         if is_marker(&comment, "imp:skip_file") {
+            return true;
+         }
+      }
+   }
+
+   /// Skips lines which are empty or only contain whitespace.
+   fn blank_lines(&self, s: &mut Ps) {
+      while let Some(n) = self.src[s.i..].iter().position(|c| !matches!(c, b' ' | b'\t' | b'\r')) {
+         if self.src[s.i + n] != b'\n' {
+            break;
+         }
+         s.i += n + 1;
+      }
+   }
+
+   /// Parses a string literal which is a statement on its own line, such as a
+   /// module docstring, and the end of that line.
+   fn docstring(&self, s: &mut Ps) -> Option<()> {
+      self.backtrack(s, |s| {
+         s.i += self.src[s.i..].iter().take(2).take_while(|c| b"rRbBuU".contains(c)).count();
+         let quote = ["\"\"\"", "'''", "\"", "'"].into_iter().find(|quote| self.string(s, quote))?;
+         while !self.string(s, quote) {
+            match self.src.get(s.i)? {
+               b'\\' => s.i += 2,
+               b'\n' if quote.len() == 1 => return None,
+               _ => s.i += 1
+            }
+         }
+         self.inline_whitespace(s);
+         let end = s.i == self.src.len() || self.comment(s).is_some() || self.string(s, "\n") || self.string(s, "\r\n");
+         end.then_some(())
+      })
+   }
+
+   pub fn start(&self, s: &mut Ps) -> Option<Vec<Item<'a>>> {
       self.whitespace(s);
+      let mut items = vec![];
+      while let Some(item) = self
+         .skipped_import(s)
+         .or_else(|| self.commented_import(s).map(Item::Import))
+         .or_else(|| self.verbatim(s))
+         .or_else(|| self.comment_block(s))
+      {
+         if let Item::Verbatim(source) = &item {
+            // A region is printed with a line break after it.
+            let end = source.i + source.slice.len();
+            s.printed = end + self.src[end..].iter().position(|c| *c == b'\n').map_or(self.src.len() - end, |i| i + 1);
+         }
+         self.whitespace(s);
+         s.rest = s.i;
+         items.push(item);
+      }
+      self.dedent_rest(s);
+      Some(items)
+   }
+
+   /// Parses an `if TYPE_CHECKING:` block at the start of the rest and the
+   /// imports at the start of its body, returning the line of the condition
+   /// and the indentation of the body with the imports.
+   pub fn type_checking(&self, s: &mut Ps) -> Option<(&'a str, &'a str, Vec<Import<'a>>)> {
+      let start = s.rest;
+      let i = s.i;
+      s.i = start;
+      let header = self.backtrack(s, |s| {
+         if !self.string(s, "if") {
+            return None;
+         }
+         self.inline_whitespace(s);
+         self.string(s, "typing.");
+         if !self.keyword(s, "TYPE_CHECKING") {
+            return None;
+         }
+         self.inline_whitespace(s);
+         if !self.string(s, ":") {
+            return None;
+         }
+         self.inline_whitespace(s);
+         (self.comment(s).is_some() || self.string(s, "\n") || self.string(s, "\r\n")).then_some(s.i)
+      });
+      let Some(body) = header else {
+         s.i = i;
+         return None;
+      };
+      let indent = self.src[body..].iter().take_while(|c| matches!(c, b' ' | b'\t')).count();
+      s.i = body + indent;
+      s.indent = indent;
       let mut imports = vec![];
-      while let Some(import) = self.import(s) {
+      while let Some(import) = self.commented_import(s) {
          s.rest = s.i;
-         self.whitespace(s);
          imports.push(import);
       }
-      Some(imports)
+      s.indent = 0;
+      if imports.is_empty() {
+         (s.i, s.rest) = (i, start);
+         return None;
+      }
+      self.dedent_rest(s);
+      let header = str::from_utf8(&self.src[start..body]).unwrap();
+      Some((header, str::from_utf8(&self.src[body..body + indent]).unwrap(), imports))
+   }
+
+   /// Moves the start of the rest back to the start of its line, keeping the
+   /// indentation of the first line.
+   fn dedent_rest(&self, s: &mut Ps) {
+      if self.indentation(s.rest).is_some_and(|indent| indent > 0) {
+         s.rest -= self.src[..s.rest].iter().rev().take_while(|c| matches!(c, b' ' | b'\t')).count();
+      }
+   }
+
+   /// Counts the blank lines between the last import and the rest of the file.
+   pub fn blank_lines_before_rest(&self, s: &Ps) -> usize {
+      let newlines = self.src[..s.rest]
+         .iter()
+         .rev()
+         .take_while(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n'))
+         .filter(|c| **c == b'\n')
+         .count();
+      newlines.saturating_sub(1)
+   }
+
+   /// Returns the line ending of the source, which is that of its first line.
+   pub fn newline(&self) -> &'static str {
+      match self.src.iter().position(|c| *c == b'\n') {
+         Some(i) if i > 0 && self.src[i - 1] == b'\r' => "\r\n",
+         _ => "\n"
+      }
    }
 
-   pub fn rest<'a>(&'a self, s: &mut Ps) -> &'a str {
+   pub fn rest(&self, s: &mut Ps) -> &'a str {
       str::from_utf8(&self.src[s.rest..self.src.len()]).unwrap()
    }
 }
+
+/// Returns whether a comment reads `# <marker>`, spaces after colons aside.
+fn is_marker(comment: &Token, marker: &str) -> bool {
+   let text = comment.to_string_lossy();
+   text.trim().trim_start_matches('#').trim().replace(": ", ":") == marker
+}
+
+fn is_encoding_declaration(comment: &[u8]) -> bool {
+   comment.windows(7).any(|w| matches!(w, b"coding:" | b"coding="))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn parse_single_import() {
+      let import = Pd::parse_single("from x import (b, a)  # c\n").unwrap();
+      assert_eq!(import.to_string(), "from x import a, b  # c");
+      assert_eq!(Pd::parse_single("import os").unwrap().to_string(), "import os");
+      assert!(Pd::parse_single("import os  \n\n").is_some());
+      assert!(Pd::parse_single("").is_none());
+      assert!(Pd::parse_single("\n").is_none());
+      assert!(Pd::parse_single("import os\nimport sys\n").is_none());
+      assert!(Pd::parse_single("import os; import sys\n").is_none());
+      assert!(Pd::parse_single("import os\nx = 1\n").is_none());
+      assert!(Pd::parse_single("x = 1\n").is_none());
+   }
+
+   #[test]
+   fn peek_import_doesnt_advance() {
+      let pd = Pd::new("import os\nx = 1\n");
+      let mut s = Ps::new();
+      assert!(pd.peek_import(&s));
+      assert_eq!(s.i, 0);
+      pd.import(&mut s).unwrap();
+      assert!(!pd.peek_import(&s));
+      assert_eq!(&pd.src[s.i..], b"x = 1\n");
+      assert!(!Pd::new("x = 1\n").peek_import(&Ps::new()));
+      assert!(Pd::new("from . import (a)").peek_import(&Ps::new()));
+   }
+
+   #[test]
+   fn tokens_with_invalid_utf8_are_printed_lossily() {
+      let comment = Token { slice: b"  # caf\xe9", i: 0 };
+      assert_eq!(comment.to_string_lossy(), "  # caf\u{fffd}");
+      assert_eq!(format!("{comment:?}"), "\"  # caf\u{fffd}\"");
+      let import = Pd::parse_single("import os\n").unwrap().with_comment(Some(comment));
+      assert_eq!(import.to_string(), "import os  # caf\u{fffd}");
+      assert!(format!("{import:?}").contains("caf\u{fffd}"));
+   }
+
+   #[test]
+   fn trailing_comma_in_parentheses() {
+      let parsed = |src| Pd::parse_single(src).map(|import| import.to_string());
+      assert_eq!(parsed("from a import (b,)").as_deref(), Some("from a import b"));
+      assert_eq!(parsed("from a import (b, c,)").as_deref(), Some("from a import b, c"));
+      assert_eq!(parsed("from a import (\n    c,\n    b,\n)\n").as_deref(), Some("from a import b, c"));
+      assert_eq!(parsed("from a import (\n    b,\n    )\n").as_deref(), Some("from a import b"));
+      assert_eq!(parsed("from a import (,)"), None);
+      assert_eq!(parsed("from a import (b,,)"), None);
+   }
+
+   #[test]
+   fn trailing_comma_without_parentheses() {
+      let Some(Import::Relative { identifiers, .. }) = Pd::parse_single("from . import a, b,\n") else { panic!() };
+      assert_eq!(identifiers.iter().map(ToString::to_string).collect::<Vec<_>>(), ["a", "b"]);
+      assert_eq!(Pd::parse_single("from . import a, b,").unwrap().to_string(), "from . import a, b");
+      assert!(Pd::parse_single("from . import ,\n").is_none());
+      assert!(Pd::parse_single("from . import a,,\n").is_none());
+   }
+}
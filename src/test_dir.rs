@@ -0,0 +1,30 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// A directory of Python files in the temporary directory, removed when
+/// dropped. It is shared by the unit tests and the tests of the command line.
+pub struct Dir(pub PathBuf);
+
+impl Dir {
+   pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+      let dir = std::env::temp_dir().join(format!("imp-{name}-{}", std::process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+      for (path, src) in files {
+         let path = dir.join(path);
+         fs::create_dir_all(path.parent().unwrap()).unwrap();
+         fs::write(path, src).unwrap();
+      }
+      Self(dir)
+   }
+
+   pub fn read(&self, path: &str) -> String {
+      fs::read_to_string(self.0.join(path)).unwrap()
+   }
+}
+
+impl Drop for Dir {
+   fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+   }
+}
@@ -0,0 +1,934 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::*;
+use crate::import::*;
+use crate::parser::*;
+use crate::test_dir::Dir;
+use crate::*;
+
+/// Sorts `src` with the options in `args`, checking that sorting the result
+/// again doesn't change it.
+fn imp(src: &str, args: &[&str]) -> String {
+   let cfg = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+   let sorted = sort_imports(src, &cfg);
+   assert_eq!(sort_imports(&sorted, &cfg), sorted, "sorting {src:?} again changed it");
+   sorted
+}
+
+/// Generates imports of a few short names, so that many of them share a
+/// module or compare equal.
+pub struct Generator(pub u64);
+
+impl Generator {
+   pub fn below(&mut self, n: usize) -> usize {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      (self.0 % n as u64) as usize
+   }
+
+   pub fn pick(&mut self, slices: &[&'static str]) -> Token<'static> {
+      Token { slice: slices[self.below(slices.len())].as_bytes(), i: self.below(8) }
+   }
+
+   pub fn path(&mut self) -> ModulePath<'static> {
+      (0..1 + self.below(2)).map(|_| self.pick(&["__future__", "a", "A", "b"])).collect()
+   }
+
+   pub fn comments(&mut self) -> CommentList<'static> {
+      (0..self.below(3)).map(|_| self.pick(&["  # x", "  # y"])).collect()
+   }
+
+   pub fn from(&mut self) -> RelativeModule<'static> {
+      match self.below(3) {
+         0 => RelativeModule::Unnamed { level: 1 + self.below(2) },
+         _ => RelativeModule::Named { level: self.below(2), path: self.path() }
+      }
+   }
+
+   pub fn import(&mut self) -> Import<'static> {
+      match self.below(3) {
+         0 => Import::Absolute {
+            modules: (0..1 + self.below(2))
+               .map(|_| Module { path: self.path(), alias: (self.below(3) == 0).then(|| self.pick(&["a", "c"])) })
+               .collect(),
+            leading: self.comments(),
+            comments: self.comments()
+         },
+         1 => Import::Relative {
+            from: self.from(),
+            identifiers: (0..1 + self.below(2))
+               .map(|_| Identifier {
+                  name: self.pick(&["a", "B", "C_D"]),
+                  alias: (self.below(3) == 0).then(|| self.pick(&["a", "B"])),
+                  leading: vec![],
+                  comments: self.comments()
+               })
+               .collect(),
+            leading: self.comments(),
+            comments: self.comments()
+         },
+         _ => Import::Wildcard { from: self.from(), leading: self.comments(), comments: self.comments() }
+      }
+   }
+}
+
+#[test]
+fn shebang_stays_on_the_first_line() {
+   assert_eq!(
+      imp("#!/usr/bin/env python3\nimport b\nimport a\n", &[]),
+      "#!/usr/bin/env python3\nfrom __future__ import annotations\nimport a\nimport b\n"
+   );
+   assert_eq!(imp("#!/usr/bin/env python3\nimport b\n", &["--profile", "none"]), "#!/usr/bin/env python3\nimport b\n");
+}
+
+#[test]
+fn comment_block_after_shebang_is_stable() {
+   assert_eq!(imp("#!/bin/py\n\n# license\n\nimport b\n", &["--profile", "none"]), "#!/bin/py\n\n# license\n\nimport b\n");
+   assert_eq!(
+      imp("# -*- coding: utf-8 -*-\n\n# license\n\nimport b\nx = 1\n", &[]),
+      "# -*- coding: utf-8 -*-\n\n# license\n\nfrom __future__ import annotations\nimport b\n\n\nx = 1\n"
+   );
+}
+
+#[test]
+fn docstring_is_skipped_by_every_pass() {
+   let src = "\"\"\"Doc.\"\"\"\nimport os\nfrom x import (os, b)\nx = 1\n";
+   assert_eq!(name_collisions(src), ["`os` is bound by both `import os` and `from x import b, os`"]);
+   assert!(debug_ast(src).contains("Absolute"));
+   assert_eq!(first_unparsed_line(src).as_deref(), Some("x = 1"));
+   assert_eq!(imp(src, &["--sort-within-parens-only"]), "\"\"\"Doc.\"\"\"\nimport os\nfrom x import (b, os)\nx = 1\n");
+}
+
+#[test]
+fn docstring_after_license_comment() {
+   assert_eq!(
+      imp("# Copyright\n\"\"\"Doc.\"\"\"\nimport b\nimport a\n", &[]),
+      "# Copyright\n\"\"\"Doc.\"\"\"\nfrom __future__ import annotations\nimport a\nimport b\n"
+   );
+}
+
+#[test]
+fn name_comments_move_with_their_names() {
+   let src = "from x import (\n    c,\n    b,  # second\n    a  # first\n)  # import\n";
+   let sorted = "from x import (\n    a,  # first\n    b,  # second\n    c,\n)  # import\n";
+   assert_eq!(imp(src, &["--profile", "none"]), sorted);
+   assert_eq!(imp(src, &["--sort-within-parens-only"]), sorted);
+   assert_eq!(imp("from x import (a, b)\n", &["--profile", "none"]), "from x import a, b\n");
+}
+
+#[test]
+fn name_comments_on_their_own_lines() {
+   let src = "from x import (  # why\n    # about b\n    b,\n    a,\n    # trailing\n)\nimport c\n";
+   assert_eq!(
+      imp(src, &["--profile", "none"]),
+      "import c\nfrom x import (\n    a,\n    # about b\n    b,\n)  # why  # trailing\n"
+   );
+   assert_eq!(
+      imp(src, &["--sort-within-parens-only"]),
+      "from x import (\n    a,\n    # about b\n    b,\n)  # why  # trailing\nimport c\n"
+   );
+}
+
+#[test]
+fn name_comments_above_names_without_inline_comments() {
+   assert_eq!(
+      imp("from x import (\n    b,  # second\n    a,\n)\nimport c\n", &["--profile", "none", "--no-inline-comments"]),
+      "import c\nfrom x import (\n    a,\n    # second\n    b,\n)\n"
+   );
+}
+
+#[test]
+fn name_comments_survive_combining() {
+   assert_eq!(
+      imp("from .p import a\nfrom .p import (\n    a,  # keep\n)\nfrom .p import a  # keep\n", &[]),
+      "from __future__ import annotations\nfrom .p import (\n    a,  # keep\n)  # keep\n"
+   );
+}
+
+#[test]
+fn name_comments_keep_crlf() {
+   assert_eq!(
+      imp("from x import (\r\n    b,  # b\r\n    a,\r\n)\r\n", &["--sort-within-parens-only"]),
+      "from x import (\r\n    a,\r\n    b,  # b\r\n)\r\n"
+   );
+   assert_eq!(
+      imp("from x import (\r\n    b,  # b\r\n    a,\r\n)\r\n", &["--profile", "none"]),
+      "from x import (\r\n    a,\r\n    b,  # b\r\n)\r\n"
+   );
+}
+
+#[test]
+fn combined_relative_imports_keep_their_comments() {
+   assert_eq!(
+      imp("from .pkg import a  # x\nfrom .pkg import b  # y\n", &["--profile", "none"]),
+      "from .pkg import a  # x\nfrom .pkg import b  # y\n"
+   );
+   assert_eq!(
+      imp("from .pkg import a  # x\nfrom .pkg import b  # y\nfrom .pkg import c  # x\n", &[]),
+      "from __future__ import annotations\nfrom .pkg import a, b, c  # x  # y\n"
+   );
+   assert_eq!(
+      imp("# about a\nfrom .pkg import a\n# about b\nfrom .pkg import b\n", &[]),
+      "from __future__ import annotations\n# about a\n# about b\nfrom .pkg import a, b\n"
+   );
+}
+
+#[test]
+fn relative_imports_of_several_modules_are_combined_once() {
+   assert_eq!(
+      imp("from .a import x\nfrom .b import r\nfrom .a import y\nfrom .b import t\n", &[]),
+      "from __future__ import annotations\nfrom .a import x, y\nfrom .b import r, t\n"
+   );
+}
+
+#[test]
+fn timings_dont_change_the_output() {
+   let src = "import b\nfrom .a import (y, x)\nimport a, c\nx = 1\n";
+   let path = Path::new("x.py");
+   let cfg = Config::default();
+   let mut timings = timings::Timings::default();
+   assert_eq!(sort_imports_timed(src, path, &cfg, &mut timings), sort_imports_at(src, path, &cfg));
+   assert!(timings.parse > Duration::ZERO && timings.render > Duration::ZERO);
+   let dir = Dir::new("timings", &[("x.py", src)]);
+   let paths = [dir.0.clone()];
+   let mut reported = vec![];
+   let changed = format_paths_timed(&paths, &cfg, false, |path, timings| {
+      reported.push((path.to_path_buf(), timings.clone()));
+   });
+   assert_eq!(changed.unwrap(), format_paths(&paths, &cfg, false).unwrap());
+   assert_eq!(reported.len(), 1);
+   assert_eq!(reported[0].0, dir.0.join("x.py"));
+   assert!(reported[0].1.parse > Duration::ZERO);
+   assert_eq!(dir.read("x.py"), src);
+}
+
+#[test]
+fn names_ordered_by_type() {
+   let src = "from x import myfunc, MyClass, CONST, A, a, Z_2\n";
+   assert_eq!(imp(src, &["--profile", "none"]), "from x import A, CONST, MyClass, Z_2, a, myfunc\n");
+   assert_eq!(imp(src, &["--profile", "none", "--order-by-type"]), "from x import CONST, Z_2, A, MyClass, a, myfunc\n");
+   assert_eq!(
+      imp("from x import b, B, Ab, aa\n", &["--profile", "none", "--order-by-type", "--sort-case-identifiers"]),
+      "from x import Ab, B, aa, b\n"
+   );
+}
+
+#[test]
+fn lines_after_imports() {
+   let src = "import b\nimport a\n\nx = 1\n";
+   let lines = |n| imp(src, &["--profile", "none", "--lines-after-imports", n]);
+   assert_eq!(lines("0"), "import a\nimport b\nx = 1\n");
+   assert_eq!(lines("1"), "import a\nimport b\n\nx = 1\n");
+   assert_eq!(lines("2"), "import a\nimport b\n\n\nx = 1\n");
+   assert_eq!(lines("-1"), "import a\nimport b\n\nx = 1\n");
+   assert_eq!(
+      imp("import b\n\n\n\nx = 1\n", &["--lines-after-imports", "-1"]),
+      "from __future__ import annotations\nimport b\n\n\n\nx = 1\n"
+   );
+   assert_eq!(imp("import b\nx = 1\n", &[]), "from __future__ import annotations\nimport b\n\n\nx = 1\n");
+}
+
+#[test]
+fn fmt_off_regions_are_left_as_written() {
+   assert_eq!(
+      imp(
+         "import b\nimport a\n# fmt: off\nimport d\nimport c\n# fmt: on\nimport f\nimport e\n\nx = 1\n",
+         &["--profile", "none"]
+      ),
+      "import a\nimport b\n# fmt: off\nimport d\nimport c\n# fmt: on\nimport e\nimport f\n\nx = 1\n"
+   );
+   assert_eq!(
+      imp("import b\n# fmt: off\nimport d\nimport c\n# fmt: on\n\nx = 1\n", &["--profile", "none"]),
+      "import b\n# fmt: off\nimport d\nimport c\n# fmt: on\n\nx = 1\n"
+   );
+   assert_eq!(imp("# fmt: off\nimport d\nimport c\n", &["--profile", "none"]), "# fmt: off\nimport d\nimport c\n");
+   assert_eq!(
+      imp("import b\n# fmt: off\nimport d\n# fmt: on\n\n\n\n# c\nx = 1\n", &[]),
+      "from __future__ import annotations\nimport b\n# fmt: off\nimport d\n# fmt: on\n\n\n# c\nx = 1\n"
+   );
+}
+
+#[test]
+fn from_imports_grouped_with_their_module() {
+   let args = ["--profile", "none", "--group-from-imports"];
+   assert_eq!(imp("import sys\nfrom os import path\nimport os\n", &args), "import os\nfrom os import path\nimport sys\n");
+   assert_eq!(
+      imp("import sys\nfrom os.path import join\nfrom os import path\nimport os\nfrom . import x\nfrom a import b\n", &args),
+      "import os\nfrom os import path\nfrom os.path import join\nimport sys\nfrom a import b\nfrom . import x\n"
+   );
+   assert_eq!(
+      imp("import sys\nfrom os import path\nimport os\n", &["--profile", "none"]),
+      "import os\nimport sys\nfrom os import path\n"
+   );
+}
+
+#[test]
+fn non_breaking_space_ends_the_imports() {
+   assert_eq!(
+      imp("import b\nimport a\nimport\u{a0}os\nimport c\n", &["--profile", "none"]),
+      "import a\nimport b\nimport\u{a0}os\nimport c\n"
+   );
+   assert_eq!(first_unparsed_line("import\u{a0}os\n").as_deref(), Some("import\u{a0}os"));
+}
+
+#[test]
+fn format_file_reports_changes() {
+   let dir = Dir::new("format-file", &[("x.py", "import b\nimport a\n")]);
+   let path = dir.0.join("x.py");
+   let cfg = Config::from_args(["--profile".into(), "none".into()]).unwrap();
+   assert!(format_file(&path, &cfg, false).unwrap());
+   assert_eq!(dir.read("x.py"), "import b\nimport a\n");
+   assert!(format_file(&path, &cfg, true).unwrap());
+   assert_eq!(dir.read("x.py"), "import a\nimport b\n");
+   assert!(!format_file(&path, &cfg, true).unwrap());
+   assert!(format_file(&dir.0.join("missing.py"), &cfg, false).is_err());
+}
+
+#[test]
+fn double_hash_and_bang_comments_are_kept() {
+   assert_eq!(
+      imp("import b\n## double\n#!bang\nimport a  ##x\n", &["--profile", "none"]),
+      "## double\n#!bang\nimport a  ##x\nimport b\n"
+   );
+   assert_eq!(imp("import b\n\n###\n\nimport a  #!x\n", &["--profile", "none"]), "import b\n\n###\n\nimport a  #!x\n");
+}
+
+#[test]
+fn collisions_between_imports() {
+   assert_eq!(
+      name_collisions("import json\nfrom simplejson import loads as json\n"),
+      ["`json` is bound by both `import json` and `from simplejson import loads as json`"]
+   );
+   assert!(name_collisions("import json\nimport json\nfrom os import path\nimport os.path\n").is_empty());
+   assert!(name_collisions("import a.b\nimport a.c\nfrom x import y as z\n").is_empty());
+}
+
+#[test]
+fn imports_without_a_trailing_newline() {
+   assert_eq!(imp("import b\nimport a", &["--profile", "none"]), "import a\nimport b\n");
+   assert_eq!(imp("import b\nimport a  # c", &["--profile", "none"]), "import a  # c\nimport b\n");
+   assert_eq!(imp("from x import (b,\n a)", &["--profile", "none"]), "from x import a, b\n");
+   assert_eq!(imp("import b", &[]), "from __future__ import annotations\nimport b\n");
+}
+
+#[test]
+fn reverse_identifiers_keeps_imports_ascending() {
+   assert_eq!(
+      imp("from y import c, d\nfrom x import a, b\nimport z\n", &["--profile", "none", "--reverse-identifiers"]),
+      "import z\nfrom x import b, a\nfrom y import d, c\n"
+   );
+}
+
+#[test]
+fn comment_spacing_and_normalization() {
+   let src = "import b  #comment\nimport a # note\n";
+   assert_eq!(imp(src, &["--profile", "none"]), "import a  # note\nimport b  #comment\n");
+   assert_eq!(imp(src, &["--profile", "none", "--normalize-comments"]), "import a  # note\nimport b  # comment\n");
+   assert_eq!(imp(src, &["--profile", "none", "--comment-spacing", "1"]), "import a # note\nimport b #comment\n");
+   assert_eq!(
+      imp("import a  #: doc\nimport b  #!x\n", &["--normalize-comments"]),
+      "from __future__ import annotations\nimport a  #: doc\nimport b  #!x\n"
+   );
+}
+
+#[test]
+fn interactive_asks_before_writing() {
+   let dir =
+      Dir::new("interactive", &[("a.py", "import b\nimport a\n"), ("b.py", "import a\n"), ("c.py", "import d\nimport c\n")]);
+   let paths = ["a.py", "b.py", "c.py"].map(|name| dir.0.join(name));
+   let cfg = Config::from_args(["--profile".into(), "none".into()]).unwrap();
+   let mut output = vec![];
+   let written = format_interactive(&paths, &cfg, &mut "y\nno\n".as_bytes(), &mut output).unwrap();
+   assert_eq!(written, [paths[0].clone()]);
+   assert_eq!(dir.read("a.py"), "import a\nimport b\n");
+   assert_eq!(dir.read("c.py"), "import d\nimport c\n");
+   let output = String::from_utf8(output).unwrap();
+   assert_eq!(output.matches("? [y/N] ").count(), 2);
+   assert!(output.contains("-import d\n import c\n+import d\n"));
+   let written = format_interactive(&paths, &cfg, &mut io::empty(), &mut vec![]).unwrap();
+   assert!(written.is_empty());
+   assert_eq!(dir.read("c.py"), "import d\nimport c\n");
+}
+
+#[test]
+fn comments_above_imports_without_inline_comments() {
+   let args = ["--profile", "none", "--no-inline-comments"];
+   assert_eq!(imp("import os  # note\n", &args), "# note\nimport os\n");
+   assert_eq!(
+      imp("import os  # note\n# above\nimport b  # after\nfrom .a import x  # x\nfrom .a import y  # y\n", &args),
+      "# above\n# after\nimport b\n# note\nimport os\n# x\nfrom .a import x\n# y\nfrom .a import y\n"
+   );
+   assert_eq!(
+      imp("from .a import x  # x\nfrom .a import y  # y\n", &["--no-inline-comments"]),
+      "from __future__ import annotations\n# x\n# y\nfrom .a import x, y\n"
+   );
+}
+
+#[test]
+fn equal_imports_keep_their_source_order() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import os  # b\nimport a\nimport os  # a\n", &args), "import a\nimport os  # b\nimport os  # a\n");
+   assert_eq!(imp("import os  # a\nimport os  # b\n", &args), "import os  # a\nimport os  # b\n");
+   assert_eq!(imp("from x import a  # 2\nfrom x import a  # 1\n", &args), "from x import a  # 2\nfrom x import a  # 1\n");
+}
+
+#[test]
+fn sort_owned_source() {
+   let cfg = Config::default();
+   let src = String::from("import b\nimport a\n");
+   let sorted = sort_imports_owned(src, &cfg);
+   assert_eq!(sorted, "from __future__ import annotations\nimport a\nimport b\n");
+}
+
+#[test]
+fn deeply_dotted_imports() {
+   let path = vec!["a"; 1000].join(".");
+   let src = format!("import {path}.b\nimport {path}\nfrom {path} import (c, b)\n");
+   let sorted = imp(&src, &["--profile", "none"]);
+   assert_eq!(sorted, format!("import {path}\nimport {path}.b\nfrom {path} import b, c\n"));
+   assert_eq!(first_unparsed_line(&src), None);
+}
+
+#[test]
+fn sort_within_parens_only_keeps_the_imports_in_place() {
+   assert_eq!(
+      imp(
+         "import z\nfrom b import (y, x)\nimport c, a\nfrom b import (\n    d,\n    c,\n)\nfrom .q import t, s\nfrom .q \
+          import (r)\nx = 1\n",
+         &["--sort-within-parens-only"]
+      ),
+      "import z\nfrom b import (x, y)\nimport c, a\nfrom b import (\n    c,\n    d,\n)\nfrom .q import t, s\nfrom .q import \
+       (r)\nx = 1\n"
+   );
+   assert_eq!(imp("from x import (c as d, a as b)\r\n", &["--sort-within-parens-only"]), "from x import (a as b, c as d)\r\n");
+}
+
+#[test]
+fn combined_comments_are_in_source_order() {
+   assert_eq!(
+      imp("# above c\nfrom .a import c  # 3\nfrom .b import x\n# above a\nfrom .a import a  # 1\nfrom .a import b  # 2\n", &[]),
+      "from __future__ import annotations\n# above c\n# above a\nfrom .a import a, b, c  # 3  # 1  # 2\nfrom .b import x\n"
+   );
+}
+
+#[test]
+fn first_unparsed_line_after_the_imports() {
+   assert_eq!(first_unparsed_line("import a\nfrom b import (c\nx = 1\n").as_deref(), Some("from b import (c"));
+   assert_eq!(first_unparsed_line("import a\n\nx = 1\n").as_deref(), Some("x = 1"));
+   assert_eq!(first_unparsed_line("import a\n"), None);
+   let long = format!("import a\nx = \"{}\"\n", "é".repeat(100));
+   assert_eq!(first_unparsed_line(&long), Some(format!("x = \"{}...", "é".repeat(52))));
+}
+
+#[test]
+fn profile_none_only_sorts() {
+   assert_eq!(
+      imp("import os, sys\nfrom .a import x\nfrom .a import y\nimport b\n\n\n\nx = 1\n", &["--profile", "none"]),
+      "import b\nimport os, sys\nfrom .a import x\nfrom .a import y\n\n\n\nx = 1\n"
+   );
+   assert_eq!(
+      imp("import os, sys\nfrom .a import x\nfrom .a import y\n", &["--profile", "none", "--lines-after-imports", "2"]),
+      "import os, sys\nfrom .a import x\nfrom .a import y\n"
+   );
+   let cfg = Config::from_args(["--profile", "none"].map(String::from)).unwrap();
+   assert!(!cfg.add_future_annotations && !cfg.combine_from_imports && !cfg.separate_imports);
+}
+
+#[test]
+fn format_paths_returns_the_changed_files() {
+   let dir = Dir::new(
+      "format-paths",
+      &[
+         ("a.py", "import b\nimport a\n"),
+         ("pkg/b.py", "import a\n"),
+         ("pkg/c.py", "import d\nimport c\n"),
+         ("pkg/d.txt", "import d\nimport c\n")
+      ]
+   );
+   let cfg = Config::from_args(["--profile", "none"].map(String::from)).unwrap();
+   let dirs = [dir.0.clone()];
+   let mut changed = format_paths(&dirs, &cfg, false).unwrap();
+   changed.sort();
+   assert_eq!(changed, [dir.0.join("a.py"), dir.0.join("pkg/c.py")]);
+   assert_eq!(format_paths(&[dir.0.join("pkg/c.py")], &cfg, true).unwrap(), [dir.0.join("pkg/c.py")]);
+   assert_eq!(format_paths(&dirs, &cfg, false).unwrap(), [dir.0.join("a.py")]);
+   assert_eq!(dir.read("pkg/d.txt"), "import d\nimport c\n");
+}
+
+#[test]
+fn any_future_name_sorts_first_and_merges() {
+   assert_eq!(
+      imp("import os\nfrom __future__ import print_function\nfrom __future__ import braces, division\n", &[]),
+      "from __future__ import annotations, braces, division, print_function\nimport os\n"
+   );
+   assert_eq!(
+      imp("import os\nfrom __future__ import print_function\n", &["--profile", "none"]),
+      "from __future__ import print_function\nimport os\n"
+   );
+}
+
+#[test]
+fn preserve_comment_spacing() {
+   let src = "import b\t# tab\nimport a # one\n";
+   assert_eq!(imp(src, &["--profile", "none", "--preserve-comment-spacing"]), "import a # one\nimport b\t# tab\n");
+   assert_eq!(imp(src, &["--profile", "none"]), "import a  # one\nimport b  # tab\n");
+   assert_eq!(
+      imp("from x import (b,   # b\n a)\n", &["--profile", "none", "--preserve-comment-spacing"]),
+      "from x import (\n    a,\n    b,   # b\n)\n"
+   );
+}
+
+#[test]
+fn cache_skips_unchanged_files() {
+   let dir = Dir::new("cache", &[("a.py", "import a\nimport b\n")]);
+   let path = dir.0.join("a.py");
+   let paths = [path.clone()];
+   let cache = dir.0.join("cache").to_string_lossy().into_owned();
+   let cfg = Config::from_args(["--profile", "none", "--cache", &cache].map(String::from)).unwrap();
+   assert!(format_paths(&paths, &cfg, false).unwrap().is_empty());
+   // Unsorted contents of the same size and modification time look unchanged.
+   let mtime = std::fs::metadata(&path).unwrap().modified().unwrap();
+   std::fs::write(&path, "import b\nimport a\n").unwrap();
+   std::fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+   assert!(format_paths(&paths, &cfg, false).unwrap().is_empty());
+   let uncached = Config { cache: None, ..cfg.clone() };
+   assert_eq!(format_paths(&paths, &uncached, false).unwrap(), paths);
+   let other = Config::from_args(["--profile", "none", "--reverse-identifiers", "--cache", &cache].map(String::from)).unwrap();
+   assert_eq!(format_paths(&paths, &other, false).unwrap(), paths);
+   std::fs::write(&path, "import bb\nimport a\n").unwrap();
+   assert_eq!(format_paths(&paths, &cfg, false).unwrap(), paths);
+}
+
+#[test]
+fn backslash_continuations() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import b\nfrom x import \\\n    a\nimport a\n", &args), "import a\nimport b\nfrom x import a\n");
+   assert_eq!(imp("import b\nimport a \\\n    + 1\n", &args), "import b\nimport a \\\n    + 1\n");
+   assert_eq!(imp("import c\nimport b\nimport a, \\\n", &args), "import b\nimport c\nimport a, \\\n");
+}
+
+#[test]
+fn submodules_stay_together() {
+   assert_eq!(
+      imp("import a.d\nimport b\nimport a.b, c, a.c\nimport ab\nimport a\nimport a_b\n", &[]),
+      "from __future__ import annotations\nimport a\nimport a.b\nimport a.c\nimport a.d\nimport a_b\nimport ab\nimport \
+       b\nimport c\n"
+   );
+}
+
+#[test]
+fn aliased_names() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("from os.path import join as j, exists\n", &args), "from os.path import exists, join as j\n");
+   assert_eq!(imp("from x import b as a, a as z, a\n", &args), "from x import a, a as z, b as a\n");
+   assert_eq!(imp("from x import (\n    b  as  c,\n    a as\\\n d,\n)\n", &args), "from x import a as d, b as c\n");
+   assert_eq!(imp("from x import a as\nimport b\n", &args), "from x import a as\nimport b\n");
+}
+
+#[test]
+fn parentheses_on_plain_imports_end_the_imports() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import c\nimport b\nimport (os)\nimport a\n", &args), "import b\nimport c\nimport (os)\nimport a\n");
+   assert_eq!(imp("import (os, sys)\n", &args), "import (os, sys)\n");
+   assert!(Pd::parse_single("import (os)").is_none());
+}
+
+#[test]
+fn unbalanced_parentheses_end_the_imports() {
+   let args = ["--profile", "none"];
+   assert_eq!(
+      imp("import c\nimport b\nfrom x import (a, b\nimport a\nx = 1\n", &args),
+      "import b\nimport c\nfrom x import (a, b\nimport a\nx = 1\n"
+   );
+   assert_eq!(imp("import c\nimport b\nfrom x import a, b)\n", &args), "import b\nimport c\nfrom x import a, b)\n");
+   assert_eq!(imp("from x import (a))\n", &args), "from x import (a))\n");
+   assert!(Pd::parse_single("from x import (a, b").is_none());
+}
+
+#[test]
+fn backslash_continuations_with_trailing_commas() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("from package import a, \\\n    c, b\n", &args), "from package import a, b, c\n");
+   assert_eq!(imp("from package import (a, \\\n    c,)\n", &args), "from package import a, c\n");
+   assert_eq!(imp("from package import a, \\\n    c, b,\n", &args), "from package import a, b, c\n");
+   assert_eq!(imp("import os, \\\n    sys\n", &args), "import os, sys\n");
+   assert_eq!(imp("from package import a, \\\r\n    c\r\n", &args), "from package import a, c\r\n");
+}
+
+#[test]
+fn remove_shadowed_imports() {
+   let args = ["--profile", "none", "--remove-shadowed"];
+   assert_eq!(imp("import json\nimport os\n\njson = None\n", &args), "import os\n\njson = None\n");
+   assert_eq!(imp("import json\n\nprint(json)\njson = None\n", &args), "import json\n\nprint(json)\njson = None\n");
+   assert_eq!(imp("from x import a, b\n\ndef a():\n    pass\n", &args), "from x import b\n\ndef a():\n    pass\n");
+   assert_eq!(imp("import json\n\njson == 1\n", &args), "import json\n\njson == 1\n");
+   assert_eq!(imp("import json\n\njson = json.loads\n", &args), "import json\n\njson = json.loads\n");
+   assert_eq!(imp("import json\n\njson = None\n", &["--profile", "none"]), "import json\n\njson = None\n");
+}
+
+#[test]
+fn crlf_files_round_trip() {
+   assert_eq!(
+      imp(
+         "#!/bin/py\r\n\"\"\"Doc.\"\"\"\r\n# c\r\nimport b  # x\r\nfrom x import (\r\n    d,  # d\r\n    a,\r\n)\r\n\r\nx = \
+          1\r\n",
+         &[]
+      ),
+      "#!/bin/py\r\n\"\"\"Doc.\"\"\"\r\nfrom __future__ import annotations\r\n# c\r\nimport b  # x\r\nfrom x import (\r\n    \
+       a,\r\n    d,  # d\r\n)\r\n\r\n\r\nx = 1\r\n"
+   );
+   let sorted = "import a  # a\r\nimport b\r\n\r\nx = 1\r\n";
+   assert_eq!(imp(sorted, &["--profile", "none"]), sorted);
+   assert_eq!(
+      imp("import b\r\nimport a  # a\r\n", &["--profile", "none", "--no-inline-comments"]),
+      "# a\r\nimport a\r\nimport b\r\n"
+   );
+}
+
+#[test]
+fn tabs_between_tokens() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("from\tx\timport\ty\nimport\tb\nimport a \t# c\n", &args), "import a  # c\nimport b\nfrom x import y\n");
+   assert_eq!(imp("from x import (\n\tb,\n\ta,\n)\n", &args), "from x import a, b\n");
+   assert_eq!(imp("if x:\n\timport b\n\timport a\n", &args), "if x:\n\timport b\n\timport a\n");
+}
+
+#[test]
+fn case_insensitive_modules_and_names() {
+   let src = "import b\nimport a.B\nimport A\nimport a.a\nfrom x import b, B, a\n";
+   assert_eq!(
+      imp(src, &["--profile", "none", "--sort-case-modules"]),
+      "import A\nimport a.a\nimport a.B\nimport b\nfrom x import B, a, b\n"
+   );
+   assert_eq!(
+      imp(src, &["--profile", "none", "--sort-case-identifiers"]),
+      "import A\nimport a.B\nimport a.a\nimport b\nfrom x import a, B, b\n"
+   );
+   assert_eq!(
+      imp("from B import x\nfrom a import y\n", &["--profile", "none", "--sort-case-modules"]),
+      "from a import y\nfrom B import x\n"
+   );
+}
+
+#[test]
+fn non_ascii_identifiers() {
+   let args = ["--profile", "none"];
+   assert_eq!(
+      imp("from café import crème, brûlée\nimport 日本\nimport a\n", &args),
+      "import a\nimport 日本\nfrom café import brûlée, crème\n"
+   );
+   assert_eq!(imp("import b\nimport a\nimport 1x\n", &args), "import a\nimport b\nimport 1x\n");
+   assert_eq!(imp("import b\nimport a\nimport a→b\n", &args), "import a\nimport b\nimport a→b\n");
+}
+
+#[test]
+fn imports_separated_by_semicolons() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import sys; import os\nimport b\n", &args), "import b\nimport os\nimport sys\n");
+   assert_eq!(imp("import os; x=1\nimport a\n", &args), "import os\nx=1\nimport a\n");
+   assert_eq!(imp("import b;\nimport a\n", &args), "import a\nimport b\n");
+   assert_eq!(first_unparsed_line("import os; x=1\n").as_deref(), Some("x=1"));
+}
+
+#[test]
+fn comment_between_module_and_import() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("from x import y  # ok\n", &args), "from x import y  # ok\n");
+   assert_eq!(imp("from x  # huh\n  import y\nimport a\n", &args), "import a\nfrom x import y  # huh\n");
+   assert_eq!(imp("from x  # huh\n  import y  # ok\n", &args), "from x import y  # huh  # ok\n");
+   assert_eq!(imp("from x  # huh\n  import *\n", &args), "from x import *  # huh\n");
+}
+
+#[test]
+fn comment_at_the_end_of_the_file() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import os  # trailing", &args), "import os  # trailing\n");
+   assert_eq!(imp("import os\n# last", &args), "import os\n# last");
+   assert_eq!(imp("import os\n\n# last", &args), "import os\n\n# last");
+   assert_eq!(Pd::parse_single("import os  # trailing").unwrap().comment().unwrap().slice, b"  # trailing");
+}
+
+#[test]
+fn debug_ast_shows_the_parsed_imports() {
+   let ast = debug_ast("import b as c\nfrom x import (a)  # c\n");
+   for slice in ["Absolute", "\"b\"", "alias: Some(\n", "\"c\"", "Relative", "\"x\"", "name: \"a\"", "\"  # c\""] {
+      assert!(ast.contains(slice), "{slice} not in {ast}");
+   }
+   assert_eq!(debug_ast("x = 1\n"), "[]");
+}
+
+#[test]
+fn comment_lines_move_with_the_import_below() {
+   let args = ["--profile", "none"];
+   assert_eq!(
+      imp("import c\n# about a\nimport a\n# about b\n# more\nimport b\nx = 1\n", &args),
+      "# about a\nimport a\n# about b\n# more\nimport b\nimport c\nx = 1\n"
+   );
+   assert_eq!(imp("import b\n# about a\nfrom . import a\n", &args), "import b\n# about a\nfrom . import a\n");
+   assert_eq!(imp("import b\n# dangling\nx = 1\n", &args), "import b\n# dangling\nx = 1\n");
+}
+
+#[test]
+fn comment_blocks_followed_by_a_blank_line_stay_in_place() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import c\n# x\n\nimport b\nimport a\n", &args), "import c\n# x\n\nimport a\nimport b\n");
+   assert_eq!(imp("# a\n\n# b\n\nimport x\n", &args), "# a\n\n# b\n\nimport x\n");
+   assert_eq!(
+      imp("import d\nimport c\n\n# x\n# y\n\nimport b\nimport a\n", &args),
+      "import c\nimport d\n\n# x\n# y\n\nimport a\nimport b\n"
+   );
+}
+
+#[test]
+fn no_future_first_sorts_future_imports_like_any_other() {
+   let src = "import b\nfrom __future__ import annotations\nimport a\n";
+   assert_eq!(imp(src, &["--profile", "none"]), "from __future__ import annotations\nimport a\nimport b\n");
+   assert_eq!(
+      imp(src, &["--profile", "none", "--no-future-first"]),
+      "import a\nimport b\nfrom __future__ import annotations\n"
+   );
+}
+
+#[test]
+fn encoding_declaration_stays_in_the_first_two_lines() {
+   assert_eq!(
+      imp("# coding: latin-1\nimport b\nimport a\n", &[]),
+      "# coding: latin-1\nfrom __future__ import annotations\nimport a\nimport b\n"
+   );
+   assert_eq!(
+      imp("#!/usr/bin/env python\n# -*- coding: utf-8 -*-\nimport b\nimport a\n", &[]),
+      "#!/usr/bin/env python\n# -*- coding: utf-8 -*-\nfrom __future__ import annotations\nimport a\nimport b\n"
+   );
+}
+
+#[test]
+fn empty_and_whitespace_only_files_stay_empty() {
+   assert_eq!(imp("", &[]), "");
+   assert_eq!(imp("  \n\n\t\n", &[]), "");
+   assert_eq!(imp("\r\n", &["--profile", "none"]), "");
+}
+
+#[test]
+fn indented_imports_are_left_alone() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("def f():\n    import x\nimport b\n", &args), "def f():\n    import x\nimport b\n");
+   assert_eq!(imp("import b\nimport a\ndef f():\n    import x\n", &args), "import a\nimport b\ndef f():\n    import x\n");
+   assert_eq!(imp("  import b\nimport a\n", &args), "  import b\nimport a\n");
+}
+
+#[test]
+fn second_alias_ends_the_imports() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import os as a as b\n", &args), "import os as a as b\n");
+   assert_eq!(
+      imp("import c\nimport b\nimport os as a as b\nimport a\n", &args),
+      "import b\nimport c\nimport os as a as b\nimport a\n"
+   );
+   assert!(Pd::parse_single("import os as a as b").is_none());
+}
+
+#[test]
+fn try_except_imports_are_left_alone() {
+   let args = ["--profile", "none"];
+   let fallback = "try:\n    import ujson as json\nexcept ImportError:\n    import json\n";
+   assert_eq!(imp(fallback, &args), fallback);
+   assert_eq!(
+      imp(&format!("import b\nimport a\n{fallback}import c\n"), &args),
+      format!("import a\nimport b\n{fallback}import c\n")
+   );
+}
+
+#[test]
+fn trim_comments_removes_the_comments_of_imports() {
+   assert_eq!(
+      imp(
+         "# about b\nimport b  # x\nfrom c import (\n    d,  # y\n)\nimport a  # z\n",
+         &["--profile", "none", "--trim-comments"]
+      ),
+      "import a\nimport b\nfrom c import d\n"
+   );
+}
+
+#[test]
+fn sort_type_checking_sorts_imports_in_place() {
+   let src = "import os\nfrom typing import TYPE_CHECKING\n\nif TYPE_CHECKING:\n    import b\n    import a\n\nx = 1\n";
+   assert_eq!(imp(src, &["--profile", "none"]), src);
+   assert_eq!(
+      imp(src, &["--profile", "none", "--sort-type-checking"]),
+      "import os\nfrom typing import TYPE_CHECKING\n\nif TYPE_CHECKING:\n    import a\n    import b\n\nx = 1\n"
+   );
+}
+
+#[test]
+fn parenthesized_wildcard_ends_the_imports() {
+   let args = ["--profile", "none"];
+   assert_eq!(imp("import b\nfrom x import (*)\nimport a\n", &args), "import b\nfrom x import (*)\nimport a\n");
+   assert!(Pd::parse_single("from x import (*)").is_none());
+   assert_eq!(first_unparsed_line("from x import (*)\n").as_deref(), Some("from x import (*)"));
+}
+
+#[test]
+fn skipped_imports_stay_in_place_as_written() {
+   assert_eq!(
+      imp("import d\nimport c\nimport b  # imp: skip\nimport z\nimport a\n", &["--profile", "none"]),
+      "import c\nimport d\nimport b  # imp: skip\nimport a\nimport z\n"
+   );
+   assert_eq!(
+      imp("from x import c\nfrom x import b  # imp: skip\nfrom x import a\nimport os, sys  # imp: skip\n", &[]),
+      "from __future__ import annotations\nfrom x import c\nfrom x import b  # imp: skip\nfrom x import a\nimport os, sys  # \
+       imp: skip\n"
+   );
+}
+
+#[test]
+fn skipped_multi_line_imports_are_kept_whole() {
+   let src = "from x import (  # imp: skip\n    b,\n    a,\n)\nimport c\n";
+   for args in [&[][..], &["--normalize-comments", "--order-by-type"], &["--trim-comments", "--reverse-identifiers"]] {
+      assert_eq!(imp(src, &[&["--profile", "none"], args].concat()), src);
+   }
+   assert_eq!(imp(src, &[]), format!("from __future__ import annotations\n{src}"));
+   let src = "import c\nfrom x import (\n    b,  # imp: skip\n    a\n)  # y\nimport a\n";
+   assert_eq!(imp(src, &["--profile", "none"]), src);
+}
+
+#[test]
+fn force_to_top_places_modules_first_in_the_given_order() {
+   let src = "import os\nimport logging\nimport abc\n";
+   assert_eq!(imp(src, &["--profile", "none", "--force-to-top", "logging"]), "import logging\nimport abc\nimport os\n");
+   assert_eq!(imp(src, &["--profile", "none", "--force-to-top", "os, logging"]), "import os\nimport logging\nimport abc\n");
+}
+
+#[test]
+fn imp_off_regions_are_left_as_written() {
+   assert_eq!(
+      imp("import d\nimport c\n# imp: off\nimport z\nimport y\n# imp: on\nimport b\nimport a\n", &["--profile", "none"]),
+      "import c\nimport d\n# imp: off\nimport z\nimport y\n# imp: on\nimport a\nimport b\n"
+   );
+}
+
+#[test]
+fn skip_file_leaves_the_file_as_written() {
+   for src in ["# imp: skip_file\nimport b\nimport a\n", "#!/bin/python\n# generated\n#imp:skip_file\n\nimport b, a\n"] {
+      assert_eq!(imp(src, &[]), src);
+   }
+   assert_eq!(imp("import b\n# imp: skip_file\nimport a\n", &["--profile", "none"]), "# imp: skip_file\nimport a\nimport b\n");
+}
+
+#[test]
+fn noqa_and_type_comments_survive_transforms() {
+   assert_eq!(
+      imp("import os, sys  # noqa: F401\nimport b, a  # type: ignore\nimport d, c  # note\n", &[]),
+      "from __future__ import annotations\nimport a  # type: ignore\nimport b  # type: ignore\nimport c  # note\nimport \
+       d\nimport os  # noqa: F401\nimport sys  # noqa: F401\n"
+   );
+   assert_eq!(
+      imp("from .a import x  # noqa: F401\nfrom .a import y  # type: ignore\n", &[]),
+      "from __future__ import annotations\nfrom .a import x, y  # noqa: F401  # type: ignore\n"
+   );
+}
+
+#[test]
+fn absolute_imports_with_distinct_comments_stay_apart() {
+   assert_eq!(
+      imp("import b  # y\nimport a  # x\nimport a  # z\n", &[]),
+      "from __future__ import annotations\nimport a  # x\nimport a  # z\nimport b  # y\n"
+   );
+}
+
+#[test]
+fn relative_imports_of_the_same_module_ordered_by_their_names() {
+   assert_eq!(
+      imp("from .a import y\nfrom .a import x as z\nfrom .a import x\nfrom .a import b, c\n", &["--profile", "none"]),
+      "from .a import b, c\nfrom .a import x\nfrom .a import x as z\nfrom .a import y\n"
+   );
+}
+
+#[test]
+fn separated_absolute_imports_keep_their_comments() {
+   assert_eq!(imp("import a, b  # noqa\n", &[]), "from __future__ import annotations\nimport a  # noqa\nimport b  # noqa\n");
+   assert_eq!(imp("import b, a  # note\n", &[]), "from __future__ import annotations\nimport a  # note\nimport b\n");
+}
+
+#[test]
+fn many_comment_blocks_are_parsed_in_linear_time() {
+   let blocks = "# c\n\n".repeat(50_000);
+   let args = ["--profile", "none"];
+   let start = std::time::Instant::now();
+   assert_eq!(imp(&format!("import b\n{blocks}import a\n"), &args), format!("import b\n{blocks}import a\n"));
+   assert_eq!(imp(&format!("import b\n{blocks}x = 1\n"), &args), format!("import b\n{blocks}x = 1\n"));
+   assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn split_noqa_moves_names_to_their_own_import() {
+   assert_eq!(
+      imp(
+         "from x import (\n    a,  # noqa: F401\n    b,\n)\nfrom x import c\nfrom y import d  # type: ignore\nfrom y import e  \
+          # note\n",
+         &["--split-noqa"]
+      ),
+      "from __future__ import annotations\nfrom x import a  # noqa: F401\nfrom x import b, c\nfrom y import d  # type: \
+       ignore\nfrom y import e  # note\n"
+   );
+}
+
+#[test]
+fn line_length_wraps_from_imports() {
+   let src = "from xxxxx import aaaaa, bbbbb, ccccc\n";
+   assert_eq!(imp(src, &["--profile", "none", "--line-length", "37"]), src);
+   assert_eq!(
+      imp(src, &["--profile", "none", "--line-length", "36"]),
+      "from xxxxx import (\n    aaaaa,\n    bbbbb,\n    ccccc,\n)\n"
+   );
+}
+
+#[test]
+fn unicode_width_measures_wide_names_in_columns() {
+   // 31 bytes, but 27 columns as each of the four characters is two wide.
+   let src = "from y import 漢字漢字  # c\n";
+   assert_eq!(imp(src, &["--profile", "none", "--line-length", "28"]), "from y import (\n    漢字漢字,\n)  # c\n");
+   assert_eq!(imp(src, &["--profile", "none", "--line-length", "28", "--unicode-width"]), src);
+}
+
+#[test]
+fn wrapped_imports_keep_their_comment_after_the_parenthesis() {
+   let wrapped = "from typing import (\n    ClassVar,\n    Final,\n)  # type: ClassVar\n";
+   let args = ["--profile", "none", "--line-length", "40"];
+   assert_eq!(imp("from typing import ClassVar, Final  # type: ClassVar\n", &args), wrapped);
+   assert_eq!(imp("from typing import (  # type: ClassVar\n    ClassVar,\n    Final,\n)\n", &args), wrapped);
+   // The comment counts towards the length, so the line stays wrapped.
+   let src = "from typing import ClassVar  # type: ClassVar\n";
+   assert_eq!(imp(src, &args), "from typing import (\n    ClassVar,\n)  # type: ClassVar\n");
+}
+
+#[test]
+fn wrap_indent() {
+   let src = "from x import (b,  # c\n a)\n";
+   assert_eq!(imp(src, &["--profile", "none", "--wrap-indent", "tab"]), "from x import (\n\ta,\n\tb,  # c\n)\n");
+   assert_eq!(imp(src, &["--profile", "none", "--wrap-indent", "2"]), "from x import (\n  a,\n  b,  # c\n)\n");
+}
+
+#[test]
+fn no_wrap_keeps_imports_on_a_single_line() {
+   let long = ["a".repeat(40), "b".repeat(40), "c".repeat(40)];
+   assert_eq!(
+      imp(&format!("from x import {}\nfrom x import {}, {}\n", long[2], long[1], long[0]), &["--profile", "none", "--no-wrap"]),
+      format!("from x import {}, {}, {}\n", long[0], long[1], long[2])
+   );
+   assert_eq!(
+      imp("from x import (\n    # above\n    b,  # why\n    a,\n)  # end\n", &["--profile", "none", "--no-wrap"]),
+      "# above\nfrom x import a, b  # why  # end\n"
+   );
+}
+
+#[test]
+fn too_long_plain_imports_are_separated() {
+   let src = "import os, sys, json  # c\nimport a, b\n";
+   assert_eq!(imp(src, &["--profile", "none"]), "import a, b\nimport json, os, sys  # c\n");
+   assert_eq!(
+      imp(src, &["--profile", "none", "--line-length", "15"]),
+      "import a, b\nimport json  # c\nimport os\nimport sys\n"
+   );
+}
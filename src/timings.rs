@@ -0,0 +1,35 @@
+use std::fmt;
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Time spent in each phase of sorting the imports of a file.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+   pub parse: Duration,
+   pub combine: Duration,
+   pub separate: Duration,
+   pub sort: Duration,
+   pub render: Duration
+}
+
+impl Timings {
+   /// Runs `f`, adding the time it took to `phase` if timings are collected.
+   pub fn time<T>(timings: Option<&mut Self>, phase: fn(&mut Self) -> &mut Duration, f: impl FnOnce() -> T) -> T {
+      let Some(timings) = timings else { return f() };
+      let start = Instant::now();
+      let v = f();
+      *phase(timings) += start.elapsed();
+      v
+   }
+}
+
+impl Display for Timings {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write!(
+         f,
+         "parse {:?}, combine {:?}, separate {:?}, sort {:?}, render {:?}",
+         self.parse, self.combine, self.separate, self.sort, self.render
+      )
+   }
+}
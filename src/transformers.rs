@@ -4,61 +4,468 @@ use crate::import::*;
 use crate::parser::*;
 
 /// Combines relative imports from the same path
-/// into a single relative statement.
+/// into a single relative statement, keeping the
+/// comments of all of them in source order.
 pub fn combine_relative_imports(imports: &mut Vec<Import>) {
-   let mut unique_relative_imports: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
-   for (i, import) in imports.iter().enumerate() {
-      if let Import::Relative { from, .. } = import {
-         if let Some(v) = unique_relative_imports.iter_mut().find_map(|(j, v)| {
-            (if let Import::Relative { from: from2, .. } = &imports[*j] { from2 } else { panic!() } == from).then_some(v)
-         }) {
-            v.push(i);
-         } else {
-            unique_relative_imports.insert(i, vec![]);
+   let mut combined: Vec<Import> = Vec::with_capacity(imports.len());
+   let mut first_import_from: BTreeMap<RelativeModule, usize> = BTreeMap::new();
+   for import in imports.drain(..) {
+      if let Import::Relative { from, identifiers, leading, comments } = &import {
+         if let Some(&i) = first_import_from.get(from) {
+            if let Import::Relative { identifiers: first, leading: first_leading, comments: first_comments, .. } =
+               &mut combined[i]
+            {
+               for identifier in identifiers {
+                  insert_identifier(first, identifier.clone());
+               }
+               first_leading.extend(leading.iter().cloned());
+               first_comments.extend(comments.iter().cloned());
+            }
+            continue;
          }
+         first_import_from.insert(from.clone(), combined.len());
       }
+      combined.push(import);
    }
-   for (i, v) in unique_relative_imports.iter() {
-      let to_combine: Vec<Token> = v
-         .iter()
-         .flat_map(|j| if let Import::Relative { identifiers, .. } = &imports[*j] { identifiers } else { panic!() })
-         .cloned()
-         .collect();
-      if let Import::Relative { identifiers: modules, .. } = &mut imports[*i] {
-         for i in to_combine {
-            modules.insert(i);
-         }
-      } else {
-         panic!()
+   for &i in first_import_from.values() {
+      combined[i].leading_comments_mut().sort_by_key(|comment| comment.i);
+      dedup_comments(combined[i].comments_mut());
+   }
+   *imports = combined;
+}
+
+/// Rewrites relative imports into absolute imports of `package`, the package
+/// containing the file. Imports which go above the top-level package are kept.
+pub fn relative_to_absolute<'a>(imports: &mut [Import<'a>], package: &'a [String]) {
+   for import in imports {
+      let (Import::Relative { from, .. } | Import::Wildcard { from, .. }) = import else { continue };
+      let (level, path) = match from {
+         RelativeModule::Named { level, path } => (*level, path.clone()),
+         RelativeModule::Unnamed { level } => (*level, vec![])
+      };
+      if level == 0 || level > package.len() {
+         continue;
       }
+      let base = package[..package.len() + 1 - level].iter().map(|name| Token { slice: name.as_bytes(), i: usize::MAX });
+      *from = RelativeModule::Named { level: 0, path: base.chain(path).collect() };
    }
-   for (_, v) in unique_relative_imports.iter() {
-      let mut i = 0;
-      imports.retain(|_| {
-         i += 1;
-         !v.contains(&(i - 1))
-      })
+}
+
+/// Rewrites from imports of modules inside `package`, the package containing
+/// the file, into relative imports.
+pub fn absolute_to_relative(imports: &mut [Import], package: &[String]) {
+   for import in imports {
+      let (Import::Relative { from, .. } | Import::Wildcard { from, .. }) = import else { continue };
+      let RelativeModule::Named { level: 0, path } = from else { continue };
+      let common = path.iter().zip(package).take_while(|(token, name)| token.slice == name.as_bytes()).count();
+      if common == 0 {
+         continue;
+      }
+      let level = package.len() + 1 - common;
+      *from = match &path[common..] {
+         [] => RelativeModule::Unnamed { level },
+         rest => RelativeModule::Named { level, path: rest.to_vec() }
+      };
    }
 }
 
-/// Separates each absolute import into single absolute imports.
+/// Removes the names which `rest`, the code after the imports, binds again at
+/// the top level before using them. Imports left without names are removed.
+pub fn remove_shadowed_imports(imports: &mut Vec<Import>, rest: &str) {
+   for import in imports.iter_mut() {
+      match import {
+         Import::Absolute { modules, .. } =>
+            modules.retain(|module| !is_shadowed(&module.alias.as_ref().unwrap_or(&module.path[0]).to_string_lossy(), rest)),
+         Import::Relative { identifiers, .. } => identifiers
+            .retain(|identifier| !is_shadowed(&identifier.alias.as_ref().unwrap_or(&identifier.name).to_string_lossy(), rest)),
+         _ => {}
+      }
+   }
+   imports.retain(|import| match import {
+      Import::Absolute { modules, .. } => !modules.is_empty(),
+      Import::Relative { identifiers, .. } => !identifiers.is_empty(),
+      _ => true
+   });
+}
+
+/// Returns whether `name` is bound by an assignment, function or class at the
+/// start of a line of `code`, and isn't mentioned before that, including in
+/// the rest of that line. Mentions in strings and comments count too.
+fn is_shadowed(name: &str, code: &str) -> bool {
+   let mut start = 0;
+   for line in code.split_inclusive('\n') {
+      let definition = line.strip_prefix("def ").or_else(|| line.strip_prefix("class ")).map(str::trim_start);
+      let after_name = definition.unwrap_or(line).strip_prefix(name).filter(|after| !after.starts_with(is_identifier));
+      let rebinding = match (definition, after_name) {
+         (Some(_), Some(after)) => Some(after),
+         (None, Some(after)) => after.trim_start().strip_prefix('=').filter(|value| !value.starts_with('=')),
+         _ => None
+      };
+      if let Some(after) = rebinding {
+         return !(mentions(&code[..start], name) || mentions(after, name));
+      }
+      start += line.len();
+   }
+   false
+}
+
+fn mentions(code: &str, name: &str) -> bool {
+   code
+      .match_indices(name)
+      .any(|(i, _)| !code[..i].ends_with(is_identifier) && !code[i + name.len()..].starts_with(is_identifier))
+}
+
+fn is_identifier(c: char) -> bool {
+   c == '_' || c.is_alphanumeric()
+}
+
+/// Separates each absolute import into single absolute imports. The comments
+/// stay with the first module, except for comments which silence linters or
+/// type checkers, such as `# noqa`, which are copied to every module.
 pub fn separate_absolute_imports(imports: &mut Vec<Import>) {
    let mut to_separate = vec![];
    for import in imports.iter_mut() {
-      if let Import::Absolute { modules, .. } = import {
-         let mut done = false;
-         modules.retain(|module| {
-            if done {
-               to_separate.push(module.clone());
-               false
+      if let Import::Absolute { modules, comments, .. } = import {
+         if let Some(first) = modules.pop_first() {
+            let directives: CommentList = comments.iter().filter(|comment| is_directive(comment)).cloned().collect();
+            to_separate
+               .extend(std::mem::replace(modules, [first].into()).into_iter().map(|module| (module, directives.clone())));
+         }
+      }
+   }
+   for (module, comments) in to_separate {
+      imports.push(Import::Absolute { modules: [module].into(), leading: vec![], comments })
+   }
+}
+
+/// Moves the comments of from imports of a single name to that name, if one of
+/// them silences a linter or type checker. This way the imports split by
+/// `split_directive_names` are split the same way after being combined again.
+pub fn directives_to_names(imports: &mut [Import]) {
+   for import in imports {
+      let Import::Relative { identifiers, leading, comments, .. } = import else { continue };
+      if identifiers.len() != 1 || !comments.iter().any(is_directive) {
+         continue;
+      }
+      let mut identifier = identifiers.pop_first().unwrap();
+      identifier.leading = [std::mem::take(leading), identifier.leading].concat();
+      identifier.comments = [identifier.comments, std::mem::take(comments)].concat();
+      identifiers.insert(identifier);
+   }
+}
+
+/// Moves each name of a from import which has a comment silencing a linter or
+/// type checker, such as `# noqa`, to a from import of its own with its
+/// comments, so that the comment doesn't apply to the other names.
+pub fn split_directive_names(imports: &mut Vec<Import>) {
+   let mut split = vec![];
+   for import in imports.iter_mut() {
+      let Import::Relative { from, identifiers, leading, comments } = import else { continue };
+      let (directives, rest): (IdentifierList, IdentifierList) =
+         std::mem::take(identifiers).into_iter().partition(|identifier| identifier.comments.iter().any(is_directive));
+      *identifiers = rest;
+      let mut directives = directives.into_iter();
+      // An import left without names becomes the import of the first name.
+      if identifiers.is_empty() {
+         let Some(first) = directives.next() else { continue };
+         leading.extend(first.leading.iter().cloned());
+         comments.splice(0..0, first.comments.iter().cloned());
+         identifiers.insert(Identifier { leading: vec![], comments: vec![], ..first });
+      }
+      for identifier in directives {
+         split.push(Import::Relative {
+            from: from.clone(),
+            leading: identifier.leading.clone(),
+            comments: identifier.comments.clone(),
+            identifiers: [Identifier { leading: vec![], comments: vec![], ..identifier }].into()
+         });
+      }
+   }
+   imports.append(&mut split);
+}
+
+/// Moves the comments of the names of from imports to the import, so that it
+/// is printed on a single line.
+pub fn unwrap_identifier_comments(imports: &mut [Import]) {
+   for import in imports {
+      let Import::Relative { identifiers, leading, comments, .. } = import else { continue };
+      if !identifiers.iter().any(Identifier::has_comments) {
+         continue;
+      }
+      *identifiers = std::mem::take(identifiers)
+         .into_iter()
+         .map(|mut identifier| {
+            leading.append(&mut identifier.leading);
+            comments.append(&mut identifier.comments);
+            identifier
+         })
+         .collect();
+      dedup_comments(leading);
+      dedup_comments(comments);
+   }
+}
+
+/// Returns whether a comment is read by a tool, such as `# noqa` or
+/// `# type: ignore`.
+fn is_directive(comment: &Token) -> bool {
+   let text = comment.to_string_lossy();
+   let text = text.trim_start().trim_start_matches('#').trim_start().to_ascii_lowercase();
+   ["noqa", "type:", "pylint:", "pyright:", "mypy:"].iter().any(|directive| text.starts_with(directive))
+}
+
+/// Moves each `from x import ...` right after the `import x` of the same
+/// top-level package, expects the imports to be sorted.
+pub fn group_from_imports_with_absolute(imports: &mut Vec<Import>) {
+   let packages: Vec<Token> = imports
+      .iter()
+      .filter(|import| matches!(import, Import::Absolute { .. }))
+      .filter_map(|import| import.top_level().cloned())
+      .collect();
+   let (from_imports, rest): (Vec<Import>, Vec<Import>) = imports.drain(..).partition(|import| {
+      !matches!(import, Import::Absolute { .. }) && import.top_level().is_some_and(|package| packages.contains(package))
+   });
+   let mut rest = rest.into_iter().peekable();
+   while let Some(import) = rest.next() {
+      let package = match &import {
+         Import::Absolute { .. } => import.top_level().cloned(),
+         _ => None
+      };
+      imports.push(import);
+      let Some(package) = package else { continue };
+      if rest.peek().is_some_and(|next| matches!(next, Import::Absolute { .. }) && next.top_level() == Some(&package)) {
+         continue;
+      }
+      imports.extend(from_imports.iter().filter(|import| import.top_level() == Some(&package)).cloned());
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::tests::Generator;
+
+   /// `combine_relative_imports` as it was written with `Vec::retain`, only
+   /// adapted to names being `Identifier`s.
+   fn combine_with_retain(imports: &mut Vec<Import>) {
+      let mut unique_relative_imports: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+      for (i, import) in imports.iter().enumerate() {
+         if let Import::Relative { from, .. } = import {
+            if let Some(v) = unique_relative_imports.iter_mut().find_map(|(j, v)| {
+               (if let Import::Relative { from: from2, .. } = &imports[*j] { from2 } else { panic!() } == from).then_some(v)
+            }) {
+               v.push(i);
             } else {
-               done = true;
-               true
+               unique_relative_imports.insert(i, vec![]);
             }
-         });
+         }
+      }
+      for (i, v) in unique_relative_imports.iter() {
+         let to_combine: Vec<Identifier> = v
+            .iter()
+            .flat_map(|j| if let Import::Relative { identifiers, .. } = &imports[*j] { identifiers } else { panic!() })
+            .cloned()
+            .collect();
+         if let Import::Relative { identifiers: modules, .. } = &mut imports[*i] {
+            for i in to_combine {
+               modules.insert(i);
+            }
+         } else {
+            panic!()
+         }
+      }
+      for (_, v) in unique_relative_imports.iter() {
+         let mut i = 0;
+         imports.retain(|_| {
+            i += 1;
+            !v.contains(&(i - 1))
+         })
+      }
+   }
+
+   /// `separate_absolute_imports` as it was written with `BTreeSet::retain`,
+   /// only adapted to imports having lists of comments.
+   fn separate_with_retain(imports: &mut Vec<Import>) {
+      let mut to_separate = vec![];
+      for import in imports.iter_mut() {
+         if let Import::Absolute { modules, .. } = import {
+            let mut done = false;
+            modules.retain(|module| {
+               if done {
+                  to_separate.push(module.clone());
+                  false
+               } else {
+                  done = true;
+                  true
+               }
+            });
+         }
+      }
+      for module in to_separate {
+         imports.push(Import::Absolute { modules: [module].into(), leading: vec![], comments: vec![] })
+      }
+   }
+
+   /// Generates imports with comments which both versions of the transformers
+   /// handle alike: comments are unique and in source order as if parsed,
+   /// only the first import from each module has comments, as the retain
+   /// version drops the others, and only one module is imported from more
+   /// than once, as the retain version leaves duplicates of the others.
+   fn comparable_imports(generator: &mut Generator) -> Vec<Import<'static>> {
+      let mut imports: Vec<Import> = (0..30).map(|_| generator.import()).collect();
+      let mut seen: Vec<RelativeModule> = vec![];
+      let mut repeated = None;
+      imports.retain_mut(|import| {
+         let Import::Relative { from, identifiers, leading, comments } = import else { return true };
+         if !seen.contains(from) {
+            seen.push(from.clone());
+            return true;
+         }
+         if repeated.get_or_insert_with(|| from.clone()) != from {
+            return false;
+         }
+         leading.clear();
+         comments.clear();
+         *identifiers = std::mem::take(identifiers)
+            .into_iter()
+            .map(|identifier| Identifier { leading: vec![], comments: vec![], ..identifier })
+            .collect();
+         true
+      });
+      for import in &mut imports {
+         as_parsed(import.leading_comments_mut());
+         as_parsed(import.comments_mut());
+         if let Import::Relative { identifiers, .. } = import {
+            *identifiers = std::mem::take(identifiers)
+               .into_iter()
+               .map(|mut identifier| {
+                  as_parsed(&mut identifier.leading);
+                  as_parsed(&mut identifier.comments);
+                  identifier
+               })
+               .collect();
+         }
+      }
+      imports
+   }
+
+   fn as_parsed(comments: &mut CommentList) {
+      let mut seen = vec![];
+      comments.retain(|comment| {
+         let unique = !seen.contains(&comment.slice);
+         seen.push(comment.slice);
+         unique
+      });
+      comments.sort_by_key(|comment| comment.i);
+   }
+
+   fn transformed<'a>(src: &'a str, transform: impl FnOnce(&mut Vec<Import<'a>>)) -> Vec<String> {
+      let mut imports = Pd::new(src).start(&mut Ps::new()).unwrap().into_iter().filter_map(Item::into_import).collect();
+      transform(&mut imports);
+      imports.iter().map(ToString::to_string).collect()
+   }
+
+   #[test]
+   fn relative_imports_made_absolute() {
+      let package = ["mypkg".to_string(), "sub".to_string()];
+      assert_eq!(
+         transformed(
+            "from . import x\nfrom .a import y\nfrom .. import z\nfrom ...up import w\nfrom c import *\n",
+            |imports| { relative_to_absolute(imports, &package) }
+         ),
+         [
+            "from mypkg.sub import x",
+            "from mypkg.sub.a import y",
+            "from mypkg import z",
+            "from ...up import w",
+            "from c import *"
+         ]
+      );
+      assert_eq!(transformed("from . import x\n", |imports| relative_to_absolute(imports, &[])), ["from . import x"]);
+   }
+
+   #[test]
+   fn absolute_imports_made_relative() {
+      let package = ["mypkg".to_string(), "sub".to_string()];
+      assert_eq!(
+         transformed(
+            "from mypkg.sub.a import y\nfrom mypkg.sub import x\nfrom mypkg import z\nfrom mypkg.other import *\nfrom other \
+             import w\nimport mypkg.sub\n",
+            |imports| absolute_to_relative(imports, &package)
+         ),
+         [
+            "from .a import y",
+            "from . import x",
+            "from .. import z",
+            "from ..other import *",
+            "from other import w",
+            "import mypkg.sub"
+         ]
+      );
+   }
+
+   fn self_aliases(imports: &[Import]) -> usize {
+      let counts = imports.iter().map(|import| match import {
+         Import::Absolute { modules, .. } =>
+            modules.iter().filter(|module| module.alias.as_ref().is_some_and(|alias| module.path == [alias.clone()])).count(),
+         Import::Relative { identifiers, .. } =>
+            identifiers.iter().filter(|identifier| identifier.alias.as_ref() == Some(&identifier.name)).count(),
+         _ => 0
+      });
+      counts.sum()
+   }
+
+   #[test]
+   fn transformers_dont_add_self_aliases() {
+      let package = ["a".to_string(), "b".to_string()];
+      for seed in 1..=200 {
+         let mut generator = Generator(seed);
+         let mut imports: Vec<Import> = (0..30).map(|_| generator.import()).collect();
+         let before = self_aliases(&imports);
+         combine_relative_imports(&mut imports);
+         separate_absolute_imports(&mut imports);
+         relative_to_absolute(&mut imports, &package);
+         absolute_to_relative(&mut imports, &package);
+         assert!(self_aliases(&imports) <= before, "{imports:?}");
       }
    }
-   for module in to_separate {
-      imports.push(Import::Absolute { modules: [module].into(), comment: None })
+
+   #[test]
+   fn directive_names_split() {
+      assert_eq!(
+         transformed("from x import (  # c\n    a,  # noqa\n    b,  # type: ignore\n)\nfrom y import c, d\n", |imports| {
+            split_directive_names(imports)
+         }),
+         ["from x import a  # noqa  # c", "from y import c, d", "from x import b  # type: ignore"]
+      );
+      assert_eq!(
+         transformed("# above\nfrom x import a  # noqa  # c\nfrom x import b\n", |imports| {
+            directives_to_names(imports);
+            combine_relative_imports(imports);
+            split_directive_names(imports);
+         }),
+         ["from x import b", "# above\nfrom x import a  # noqa  # c"]
+      );
+   }
+
+   fn printed(imports: &[Import]) -> Vec<String> {
+      imports.iter().map(ToString::to_string).collect()
+   }
+
+   #[test]
+   fn rewritten_removals_match_retain() {
+      let mut comments = 0;
+      for seed in 1..=200 {
+         let imports = comparable_imports(&mut Generator(seed));
+         comments += imports.iter().filter(|import| !import.comments().is_empty()).count();
+         let (mut expected, mut actual) = (imports.clone(), imports.clone());
+         combine_with_retain(&mut expected);
+         combine_relative_imports(&mut actual);
+         assert_eq!(printed(&actual), printed(&expected), "combining {imports:?}");
+         let (mut expected, mut actual) = (imports.clone(), imports.clone());
+         separate_with_retain(&mut expected);
+         separate_absolute_imports(&mut actual);
+         assert_eq!(printed(&actual), printed(&expected), "separating {imports:?}");
+      }
+      assert!(comments > 1000, "only {comments} imports with comments");
    }
 }
@@ -0,0 +1,168 @@
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+#[path = "../src/test_dir.rs"]
+mod test_dir;
+
+use test_dir::Dir;
+
+/// Runs imp with `args`, piping `stdin` to it.
+fn imp(args: &[&str], stdin: &str) -> Output {
+   let mut child = Command::new(env!("CARGO_BIN_EXE_imp"))
+      .args(args)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .unwrap();
+   // imp doesn't read stdin when given files, or when the options are invalid.
+   let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+   child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+   String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn check_reports_unsorted_files() {
+   let dir =
+      Dir::new("check", &[("a.py", "import b\nimport a\n"), ("b/c.py", "import a\n"), ("b/d.py", "import d\nimport c\n")]);
+   let path = dir.0.to_str().unwrap();
+   let output = imp(&["--profile", "none", "--check", path], "");
+   assert_eq!(output.status.code(), Some(1));
+   let report = stderr(&output);
+   assert!(report.contains(&format!("would reformat {}", dir.0.join("a.py").display())));
+   assert!(report.contains(&format!("would reformat {}", dir.0.join("b/d.py").display())));
+   assert!(report.ends_with("2 files would be reformatted.\n"), "{report}");
+   let output = imp(&["--profile", "none", "--check", "--quiet", path], "");
+   assert_eq!((output.status.code(), stderr(&output)), (Some(1), String::new()));
+   assert_eq!(dir.read("a.py"), "import b\nimport a\n");
+
+   let sorted = Dir::new("check-sorted", &[("a.py", "import a\n"), ("b.py", "import b\n")]);
+   let output = imp(&["--profile", "none", "--check", sorted.0.to_str().unwrap()], "");
+   assert_eq!((output.status.code(), stderr(&output)), (Some(0), "All 2 files are correctly sorted.\n".into()));
+}
+
+#[test]
+fn in_place_rewrites_files() {
+   let dir = Dir::new("in-place", &[("a.py", "import b\nimport a\n")]);
+   let output = imp(&["--profile", "none", "-i", dir.0.join("a.py").to_str().unwrap()], "");
+   assert_eq!((output.status.code(), output.stdout.len()), (Some(0), 0));
+   assert_eq!(dir.read("a.py"), "import a\nimport b\n");
+   let output = imp(&["-i"], "import b\n");
+   assert_eq!((output.status.code(), stderr(&output)), (Some(2), "imp: `--in-place` needs files to format\n".into()));
+}
+
+#[test]
+fn warn_collisions_leaves_the_output_unchanged() {
+   let src = "import json\nfrom simplejson import loads as json\n";
+   let output = imp(&["--profile", "none", "--warn-collisions"], src);
+   assert_eq!(String::from_utf8_lossy(&output.stdout), src);
+   assert_eq!(stderr(&output), "-: `json` is bound by both `import json` and `from simplejson import loads as json`\n");
+   let output = imp(&["--profile", "none", "--warn-collisions"], "import json\n");
+   assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn warn_mixed_indent_compares_the_wrap_indent() {
+   let src = "import a\nif a:\n    pass\n";
+   let output = imp(&["--profile", "none", "--wrap-indent", "tab", "--warn-mixed-indent"], src);
+   assert_eq!(String::from_utf8_lossy(&output.stdout), src);
+   assert_eq!(stderr(&output), "-: indented with spaces, but imports are wrapped with a tab\n");
+   let output = imp(&["--profile", "none", "--warn-mixed-indent"], src);
+   assert_eq!(stderr(&output), "");
+   let output = imp(&["--profile", "none", "--warn-mixed-indent"], "import a\nif a:\n\tpass\n");
+   assert_eq!(stderr(&output), "-: indented with tabs, but imports are wrapped with spaces\n");
+}
+
+#[test]
+fn verbose_in_place_lists_changed_files() {
+   let dir = Dir::new("verbose", &[("a.py", "import b\nimport a\n"), ("b.py", "import a\n")]);
+   let output = imp(&["--profile", "none", "-i", "-v", dir.0.to_str().unwrap()], "");
+   let report = stderr(&output);
+   assert!(report.contains(&format!("formatted {}\n", dir.0.join("a.py").display())), "{report}");
+   assert!(!report.contains(&format!("formatted {}", dir.0.join("b.py").display())), "{report}");
+   assert!(report.ends_with("1 file formatted.\n"), "{report}");
+}
+
+#[test]
+fn contradictory_options_are_rejected() {
+   let output = imp(&["-q", "-v"], "import a\n");
+   assert_eq!((output.status.code(), stderr(&output)), (Some(2), "imp: `--quiet` can't be used with `--verbose`\n".into()));
+   assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn code_is_formatted_without_files() {
+   let output = imp(&["--code", "import sys, os"], "import ignored\n");
+   assert_eq!(output.status.code(), Some(0));
+   assert_eq!(String::from_utf8_lossy(&output.stdout), "from __future__ import annotations\nimport os\nimport sys\n");
+   let output = imp(&["-c", "import b\nimport a", "--profile", "none"], "");
+   assert_eq!(String::from_utf8_lossy(&output.stdout), "import a\nimport b\n");
+}
+
+#[test]
+fn verbose_shows_where_parsing_stopped() {
+   let output = imp(&["-v", "--stdin-filename", "x.py"], "import b\nimport a\nfoo(\n");
+   assert!(stderr(&output).starts_with("x.py: stopped at: foo(\n"), "{}", stderr(&output));
+   let output = imp(&["--stdin-filename", "x.py"], "import b\nimport a\nfoo(\n");
+   assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn relative_imports_resolved_against_the_package_of_the_file() {
+   let dir = Dir::new("relative", &[("mypkg/__init__.py", ""), ("mypkg/sub/__init__.py", ""), ("mypkg/sub/m.py", "")]);
+   let path = dir.0.join("mypkg/sub/m.py");
+   let output = imp(
+      &["--profile", "none", "--relative-to-absolute", "--stdin-filename", path.to_str().unwrap()],
+      "from . import x\nfrom ..a import y\n"
+   );
+   assert_eq!(String::from_utf8_lossy(&output.stdout), "from mypkg.a import y\nfrom mypkg.sub import x\n");
+}
+
+#[test]
+fn imports_of_the_package_of_the_file_made_relative() {
+   let dir = Dir::new("absolute", &[("mypkg/__init__.py", ""), ("mypkg/sub/__init__.py", ""), ("mypkg/sub/m.py", "")]);
+   let path = dir.0.join("mypkg/sub/m.py");
+   let args = ["--profile", "none", "--absolute-to-relative", "--stdin-filename", path.to_str().unwrap()];
+   let output = imp(&args, "from mypkg.sub.a import y\nfrom mypkg import x\nfrom other import z\n");
+   assert_eq!(String::from_utf8_lossy(&output.stdout), "from other import z\nfrom .a import y\nfrom .. import x\n");
+}
+
+#[test]
+fn check_reads_stdin() {
+   let output = imp(&["--profile", "none", "--check", "--stdin-filename", "f.py"], "import b\nimport a\n");
+   assert_eq!(output.status.code(), Some(1));
+   assert!(output.stdout.is_empty());
+   assert_eq!(stderr(&output), "would reformat f.py\n1 file would be reformatted.\n");
+   let output = imp(&["--profile", "none", "--check"], "import a\nimport b\n");
+   assert_eq!((output.status.code(), stderr(&output)), (Some(0), "All 1 file is correctly sorted.\n".into()));
+   assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn debug_ast_is_printed_to_stderr() {
+   let output = imp(&["--profile", "none", "--debug-ast"], "import b\nimport a\n");
+   assert_eq!(String::from_utf8_lossy(&output.stdout), "import a\nimport b\n");
+   let ast = stderr(&output);
+   assert!(ast.starts_with("-: [\n    Absolute {") && ast.find("\"b\"") < ast.find("\"a\""), "{ast}");
+}
+
+#[test]
+fn smart_quotes_in_comments_dont_panic() {
+   let dir = Dir::new("smart-quotes", &[]);
+   let path = dir.0.join("a.py");
+   fs::write(&path, b"# -*- coding: cp1252 -*-\nimport b  # \x93quoted\x94\nimport a\n").unwrap();
+   let output = imp(&["--profile", "none", path.to_str().unwrap()], "");
+   if cfg!(feature = "encoding") {
+      assert!(output.status.success(), "{}", stderr(&output));
+      assert_eq!(output.stdout, b"# -*- coding: cp1252 -*-\nimport a\nimport b  # \x93quoted\x94\n");
+   } else {
+      assert_eq!(output.status.code(), Some(2));
+      assert!(stderr(&output).contains("invalid utf-8"));
+   }
+}